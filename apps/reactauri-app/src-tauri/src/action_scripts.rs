@@ -0,0 +1,524 @@
+use crate::client_requests;
+use crate::reactauri_core_server::{self, CommandWithClientId};
+use crate::redaction;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+use tauri::Emitter;
+use tauri_plugin_store::StoreExt;
+use uuid::Uuid;
+
+const SCRIPTS_STORE: &str = "action-scripts.json";
+const SCRIPTS_KEY: &str = "scripts";
+
+// Where a `state.action.complete` names its action, the action type when the
+// client sends no name. Recording and replay both go by these.
+const NAME_FIELDS: &[&str] = &["/name", "/action/type"];
+
+// One recorded `state.action.complete`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptAction {
+    // Action type
+    pub name: String,
+    pub action: Value,
+    // Time since the previous action when it was recorded
+    pub delay_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActionScript {
+    pub id: String,
+    pub name: String,
+    // Client the actions were recorded from
+    pub client_id: Option<String>,
+    pub created_at: String,
+    pub actions: Vec<ScriptAction>,
+}
+
+struct Recording {
+    script: ActionScript,
+    last_action_at: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayOptions {
+    // Fixed delay between actions, the recorded delays are used when None
+    #[serde(default)]
+    pub delay_ms: Option<u64>,
+    // Divides the recorded delays, 2.0 replays twice as fast
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+    // Wait for the client to report each action as completed before the next
+    #[serde(default = "default_wait_for_completion")]
+    pub wait_for_completion: bool,
+    #[serde(default)]
+    pub completion_timeout_ms: Option<u64>,
+    pub stop_on_error: bool,
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+fn default_wait_for_completion() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayError {
+    pub index: usize,
+    pub name: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayResult {
+    pub script_id: String,
+    pub client_id: String,
+    pub dispatched: usize,
+    pub errors: Vec<ReplayError>,
+    // Set when the replay ended early, on error or by `stop_action_replay`
+    pub stopped: bool,
+}
+
+// Payload of the `actionReplayProgress` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ReplayProgress<'a> {
+    script_id: &'a str,
+    client_id: &'a str,
+    index: usize,
+    total: usize,
+    name: &'a str,
+    error: Option<&'a str>,
+}
+
+// Recordings in progress, by client id
+static RECORDINGS: OnceLock<Mutex<HashMap<String, Recording>>> = OnceLock::new();
+// Clients a replay is running against, and those asked to stop
+static ACTIVE_REPLAYS: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+static STOPPED_REPLAYS: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+
+fn get_recordings() -> &'static Mutex<HashMap<String, Recording>> {
+    RECORDINGS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn get_active_replays() -> &'static Mutex<HashSet<String>> {
+    ACTIVE_REPLAYS.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+fn get_stopped_replays() -> &'static Mutex<HashSet<String>> {
+    STOPPED_REPLAYS.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+fn load_scripts(app: &AppHandle) -> Result<Vec<ActionScript>, String> {
+    let store = app
+        .store(SCRIPTS_STORE)
+        .map_err(|e| format!("Failed to open action scripts: {}", e))?;
+    match store.get(SCRIPTS_KEY) {
+        Some(scripts) => serde_json::from_value(scripts).map_err(|e| format!("Failed to read action scripts: {}", e)),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn save_scripts(app: &AppHandle, scripts: &[ActionScript]) -> Result<(), String> {
+    let store = app
+        .store(SCRIPTS_STORE)
+        .map_err(|e| format!("Failed to open action scripts: {}", e))?;
+    store.set(SCRIPTS_KEY, serde_json::to_value(scripts).map_err(|e| e.to_string())?);
+    store
+        .save()
+        .map_err(|e| format!("Failed to save action scripts: {}", e))
+}
+
+// Run `f` against the script with `id`, saving all scripts when `f` succeeds
+fn update_script<F: FnOnce(&mut ActionScript) -> Result<(), String>>(
+    app: &AppHandle,
+    id: &str,
+    f: F,
+) -> Result<ActionScript, String> {
+    let mut scripts = load_scripts(app)?;
+    let script = scripts
+        .iter_mut()
+        .find(|script| script.id == id)
+        .ok_or_else(|| format!("Action script {} not found", id))?;
+    f(script)?;
+    let updated = script.clone();
+    save_scripts(app, &scripts)?;
+    Ok(updated)
+}
+
+// Scripts are stored as recorded so replays dispatch the actions the app
+// sent, the UI gets them with the redaction rules applied
+fn script_info(mut script: ActionScript) -> ActionScript {
+    for entry in &mut script.actions {
+        let shown = redaction::redact_copy(
+            "state.action.complete",
+            &serde_json::json!({ "name": entry.name, "action": entry.action }),
+        );
+        entry.action = shown["action"].clone();
+    }
+    script
+}
+
+fn action_name(payload: &Value) -> String {
+    NAME_FIELDS
+        .iter()
+        .find_map(|field| payload.pointer(field).and_then(|v| v.as_str()))
+        .unwrap_or("")
+        .to_string()
+}
+
+// Time to wait before dispatching `entry`
+fn replay_delay(entry: &ScriptAction, options: &ReplayOptions) -> Duration {
+    match options.delay_ms {
+        Some(delay_ms) => Duration::from_millis(delay_ms),
+        None => Duration::from_millis(entry.delay_ms).div_f64(options.speed.max(0.01)),
+    }
+}
+
+fn take_stop(client_id: &str) -> bool {
+    get_stopped_replays().lock().unwrap().remove(client_id)
+}
+
+fn check_index(script: &ActionScript, index: usize) -> Result<(), String> {
+    if index >= script.actions.len() {
+        return Err(format!(
+            "Action {} out of range, script {} has {} actions",
+            index,
+            script.id,
+            script.actions.len()
+        ));
+    }
+    Ok(())
+}
+
+// Add a `state.action.complete` payload to the client's recording, if one is running
+pub fn record(client_id: &str, payload: &Value) {
+    let mut recordings = get_recordings().lock().unwrap();
+    let Some(recording) = recordings.get_mut(client_id) else {
+        return;
+    };
+    let Some(action) = payload.get("action") else {
+        return;
+    };
+
+    let now = Instant::now();
+    let delay_ms = recording
+        .last_action_at
+        .map(|last| now.duration_since(last).as_millis() as u64)
+        .unwrap_or(0);
+    recording.last_action_at = Some(now);
+    recording.script.actions.push(ScriptAction {
+        name: action_name(payload),
+        action: action.clone(),
+        delay_ms,
+    });
+}
+
+#[tauri::command]
+pub fn start_action_recording(client_id: String, name: String) -> Result<(), String> {
+    let mut recordings = get_recordings().lock().unwrap();
+    if recordings.contains_key(&client_id) {
+        return Err(format!("Already recording actions of client {}", client_id));
+    }
+    recordings.insert(
+        client_id.clone(),
+        Recording {
+            script: ActionScript {
+                id: Uuid::new_v4().to_string(),
+                name,
+                client_id: Some(client_id),
+                created_at: chrono::Utc::now().to_rfc3339(),
+                actions: Vec::new(),
+            },
+            last_action_at: None,
+        },
+    );
+    Ok(())
+}
+
+// Stop recording and save the script
+#[tauri::command]
+pub fn stop_action_recording(app: AppHandle, client_id: String) -> Result<ActionScript, String> {
+    let recording = get_recordings()
+        .lock()
+        .unwrap()
+        .remove(&client_id)
+        .ok_or_else(|| format!("Not recording actions of client {}", client_id))?;
+
+    let mut scripts = load_scripts(&app)?;
+    scripts.push(recording.script.clone());
+    save_scripts(&app, &scripts)?;
+    info!(
+        "Recorded {} actions of client {} as {}",
+        recording.script.actions.len(),
+        client_id,
+        recording.script.name
+    );
+    Ok(script_info(recording.script))
+}
+
+#[tauri::command]
+pub fn list_action_scripts(app: AppHandle) -> Result<Vec<ActionScript>, String> {
+    Ok(load_scripts(&app)?.into_iter().map(script_info).collect())
+}
+
+#[tauri::command]
+pub fn rename_action_script(app: AppHandle, id: String, name: String) -> Result<ActionScript, String> {
+    update_script(&app, &id, |script| {
+        script.name = name;
+        Ok(())
+    })
+    .map(script_info)
+}
+
+#[tauri::command]
+pub fn delete_action_script(app: AppHandle, id: String) -> Result<(), String> {
+    let mut scripts = load_scripts(&app)?;
+    let before = scripts.len();
+    scripts.retain(|script| script.id != id);
+    if scripts.len() == before {
+        return Err(format!("Action script {} not found", id));
+    }
+    save_scripts(&app, &scripts)
+}
+
+#[tauri::command]
+pub fn move_script_action(app: AppHandle, id: String, from: usize, to: usize) -> Result<ActionScript, String> {
+    update_script(&app, &id, |script| {
+        check_index(script, from)?;
+        check_index(script, to)?;
+        let action = script.actions.remove(from);
+        script.actions.insert(to, action);
+        Ok(())
+    })
+    .map(script_info)
+}
+
+#[tauri::command]
+pub fn remove_script_action(app: AppHandle, id: String, index: usize) -> Result<ActionScript, String> {
+    update_script(&app, &id, |script| {
+        check_index(script, index)?;
+        script.actions.remove(index);
+        Ok(())
+    })
+    .map(script_info)
+}
+
+// Replace the action dispatched at `index`, and optionally its delay
+#[tauri::command]
+pub fn update_script_action(
+    app: AppHandle,
+    id: String,
+    index: usize,
+    action: Value,
+    delay_ms: Option<u64>,
+) -> Result<ActionScript, String> {
+    update_script(&app, &id, |script| {
+        check_index(script, index)?;
+        let entry = &mut script.actions[index];
+        if let Some(name) = action.get("type").and_then(|t| t.as_str()) {
+            entry.name = name.to_string();
+        }
+        entry.action = action;
+        if let Some(delay_ms) = delay_ms {
+            entry.delay_ms = delay_ms;
+        }
+        Ok(())
+    })
+    .map(script_info)
+}
+
+// Dispatch the actions of a script to a client, one by one
+pub async fn replay(
+    app: AppHandle,
+    script: &ActionScript,
+    client_id: &str,
+    options: &ReplayOptions,
+) -> ReplayResult {
+    let timeout = options
+        .completion_timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or(client_requests::DEFAULT_TIMEOUT);
+    let mut result = ReplayResult {
+        script_id: script.id.clone(),
+        client_id: client_id.to_string(),
+        dispatched: 0,
+        errors: Vec::new(),
+        stopped: false,
+    };
+
+    for (index, entry) in script.actions.iter().enumerate() {
+        if take_stop(client_id) {
+            result.stopped = true;
+            break;
+        }
+        if index > 0 {
+            tokio::time::sleep(replay_delay(entry, options)).await;
+            // Asked to stop while waiting
+            if take_stop(client_id) {
+                result.stopped = true;
+                break;
+            }
+        }
+
+        let payload = serde_json::json!({ "action": entry.action });
+        let outcome = if options.wait_for_completion {
+            client_requests::send_and_observe(
+                app.clone(),
+                client_id,
+                "state.action.dispatch",
+                payload,
+                "state.action.complete",
+                NAME_FIELDS,
+                &entry.name,
+                timeout,
+            )
+            .await
+            .map(|_| ())
+        } else if reactauri_core_server::is_client_connected(client_id).await {
+            let command = CommandWithClientId {
+                r#type: "state.action.dispatch".to_string(),
+                payload,
+                client_id: client_id.to_string(),
+                important: false,
+                date: Some(chrono::Utc::now().to_rfc3339()),
+                delta_time: Some(0),
+            };
+            reactauri_core_server::send_command(app.clone(), command).await;
+            Ok(())
+        } else {
+            Err(format!("Client {} is not connected", client_id))
+        };
+
+        result.dispatched += 1;
+        let error = outcome.err();
+        let _ = app.emit(
+            "actionReplayProgress",
+            &ReplayProgress {
+                script_id: &script.id,
+                client_id,
+                index,
+                total: script.actions.len(),
+                name: &entry.name,
+                error: error.as_deref(),
+            },
+        );
+        if let Some(message) = error {
+            warn!("Replaying {} to client {} failed at {}: {}", script.name, client_id, entry.name, message);
+            result.errors.push(ReplayError {
+                index,
+                name: entry.name.clone(),
+                message,
+            });
+            if options.stop_on_error {
+                result.stopped = true;
+                break;
+            }
+        }
+    }
+    result
+}
+
+#[tauri::command]
+pub async fn replay_action_script(
+    app: AppHandle,
+    id: String,
+    client_id: String,
+    options: ReplayOptions,
+) -> Result<ReplayResult, String> {
+    let script = load_scripts(&app)?
+        .into_iter()
+        .find(|script| script.id == id)
+        .ok_or_else(|| format!("Action script {} not found", id))?;
+
+    if !get_active_replays().lock().unwrap().insert(client_id.clone()) {
+        return Err(format!("A replay to client {} is already running", client_id));
+    }
+    get_stopped_replays().lock().unwrap().remove(&client_id);
+
+    let result = replay(app, &script, &client_id, &options).await;
+
+    get_active_replays().lock().unwrap().remove(&client_id);
+    get_stopped_replays().lock().unwrap().remove(&client_id);
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn stop_action_replay(client_id: String) -> bool {
+    if !get_active_replays().lock().unwrap().contains(&client_id) {
+        return false;
+    }
+    get_stopped_replays().lock().unwrap().insert(client_id);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn options(delay_ms: Option<u64>, speed: f64) -> ReplayOptions {
+        serde_json::from_value(json!({ "delayMs": delay_ms, "speed": speed, "stopOnError": false })).unwrap()
+    }
+
+    #[test]
+    fn records_actions_as_sent_and_named_like_the_completion() {
+        redaction::use_test_rules();
+        start_action_recording("recorder".to_string(), "login".to_string()).unwrap();
+        record("recorder", &json!({ "name": "LOGIN", "action": { "type": "login/start", "token": "secret-1" } }));
+        record("recorder", &json!({ "action": { "type": "login/done" } }));
+        record("recorder", &json!({ "name": "ignored" }));
+        record("somebody-else", &json!({ "action": { "type": "other" } }));
+        let script = get_recordings().lock().unwrap().remove("recorder").unwrap().script;
+
+        let names: Vec<&str> = script.actions.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["LOGIN", "login/done"]);
+        assert_eq!(script.actions[0].delay_ms, 0);
+        assert_eq!(script.actions[0].action["token"], json!("secret-1"));
+        assert_eq!(script_info(script).actions[0].action["token"], json!("[REDACTED]"));
+    }
+
+    #[test]
+    fn refuses_a_second_recording_of_a_client() {
+        start_action_recording("twice".to_string(), "a".to_string()).unwrap();
+        assert!(start_action_recording("twice".to_string(), "b".to_string()).is_err());
+        get_recordings().lock().unwrap().remove("twice");
+    }
+
+    #[test]
+    fn names_actions_by_type_when_the_completion_has_no_name() {
+        assert_eq!(action_name(&json!({ "name": "A", "action": { "type": "b" } })), "A");
+        assert_eq!(action_name(&json!({ "action": { "type": "b" } })), "b");
+        assert_eq!(action_name(&json!({})), "");
+    }
+
+    #[test]
+    fn delays_by_the_fixed_delay_or_the_scaled_recorded_one() {
+        let entry = ScriptAction {
+            name: "a".to_string(),
+            action: json!({ "type": "a" }),
+            delay_ms: 1000,
+        };
+        assert_eq!(replay_delay(&entry, &options(Some(10), 1.0)), Duration::from_millis(10));
+        assert_eq!(replay_delay(&entry, &options(None, 2.0)), Duration::from_millis(500));
+        assert_eq!(replay_delay(&entry, &options(None, 0.0)), Duration::from_millis(100_000));
+    }
+
+    #[test]
+    fn stop_requests_are_taken_once() {
+        get_stopped_replays().lock().unwrap().insert("stopping".to_string());
+        assert!(take_stop("stopping"));
+        assert!(!take_stop("stopping"));
+    }
+}
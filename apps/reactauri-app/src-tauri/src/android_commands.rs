@@ -1,3 +1,4 @@
+use log::{debug, error, warn};
 use tauri::{AppHandle, Emitter};
 
 #[tauri::command]
@@ -10,7 +11,7 @@ pub async fn get_device_list(app_handle: tauri::AppHandle) -> Result<String, Str
     let result = String::from_utf8(output.stdout)
         .map_err(|e| format!("Failed to parse adb output: {}", e))?;
     
-    println!("get_device_list: {}", result);
+    debug!("get_device_list: {}", result);
     let _ = app_handle.emit("device_list", result.clone());
     
     Ok(result)
@@ -25,10 +26,10 @@ pub async fn reverse_tunnel_device(device_id: String, reactotron_port: u16, metr
         .map_err(|e| format!("Failed to reverse tunnel reactotron port: {}", e))?;
     
     if !reactauri_result.status.success() {
-        println!("Reactotron reverse tunnel failed: {:?}", reactauri_result);
+        warn!("Reactotron reverse tunnel failed: {:?}", reactauri_result);
         return Err(format!("Reactotron reverse tunnel failed: {}", String::from_utf8_lossy(&reactauri_result.stderr)));
     } else {
-        println!("Reactotron reverse tunnel success: {:?}", reactauri_result);
+        debug!("Reactotron reverse tunnel success: {:?}", reactauri_result);
     }
     
     // Reverse tunnel for metro
@@ -38,10 +39,10 @@ pub async fn reverse_tunnel_device(device_id: String, reactotron_port: u16, metr
         .map_err(|e| format!("Failed to reverse tunnel metro port: {}", e))?;
     
     if !metro_result.status.success() {
-        println!("Metro reverse tunnel failed: {:?}", metro_result);
+        warn!("Metro reverse tunnel failed: {:?}", metro_result);
         return Err(format!("Metro reverse tunnel failed: {}", String::from_utf8_lossy(&metro_result.stderr)));
     } else {
-        println!("Metro reverse tunnel success: {:?}", metro_result);
+        debug!("Metro reverse tunnel success: {:?}", metro_result);
     }
     
     Ok(())
@@ -54,11 +55,11 @@ pub async fn reload_app(device_id: String) -> Result<(), String> {
         .output()
         .map_err(|e| format!("Failed to reload app: {}", e))?;
     
-    println!("reload_app: {:?}", result);
+    debug!("reload_app: {:?}", result);
     if !result.status.success() {
         return Err(format!("Reload app failed: {}", String::from_utf8_lossy(&result.stderr)));
     } else {
-        println!("Reload app success: {:?}", result.stdout);
+        debug!("Reload app success: {:?}", result.stdout);
     }
     
     Ok(())
@@ -72,10 +73,10 @@ pub async fn shake_device(device_id: String) -> Result<(), String> {
         .map_err(|e| format!("Failed to shake device: {}", e))?;
     
     if !result.status.success() {
-        println!("Shake device failed: {:?}", result);
+        warn!("Shake device failed: {:?}", result);
         return Err(format!("Shake device failed: {}", String::from_utf8_lossy(&result.stderr)));
     } else {
-        println!("Shake device success: {:?}", result);
+        debug!("Shake device success: {:?}", result);
     }
     
     Ok(())
@@ -100,13 +101,13 @@ pub fn start_device_tracking_internal(app_handle: tauri::AppHandle) -> Result<()
             
             for line in reader.lines() {
                 if let Ok(line) = line {
-                    println!("Got adb track-devices output: {}", line);
+                    debug!("Got adb track-devices output: {}", line);
                     // TypeScript 버전과 동일하게 디바이스 목록을 가져와서 프론트엔드에 전송
                     if let Ok(output) = std::process::Command::new("adb")
                         .arg("devices")
                         .output() {
                         if let Ok(device_list) = String::from_utf8(output.stdout) {
-                            println!("Got adb device lis 전송t: {}", device_list);
+                            debug!("Got adb device list: {}", device_list);
                             let _ = app_handle_clone.emit("device_list", device_list);
                         }
                     }
@@ -120,14 +121,14 @@ pub fn start_device_tracking_internal(app_handle: tauri::AppHandle) -> Result<()
             
             for line in reader.lines() {
                 if let Ok(line) = line {
-                    println!("adb track-devices stderr: {}", line);
+                    warn!("adb track-devices stderr: {}", line);
                 }
             }
         }
         
         let status = child.wait().expect("Failed to wait for adb process");
         if !status.success() {
-            println!("adb track-devices process exited with status: {}", status);
+            error!("adb track-devices process exited with status: {}", status);
         }
     });
     
@@ -0,0 +1,275 @@
+use crate::payload_store::json_size;
+use crate::reactauri_core_server::Command;
+use crate::redaction;
+use crate::stats::Distribution;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+
+// Responses kept for analytics, the oldest are dropped first
+const MAX_RECORDS: usize = 50_000;
+
+struct ApiRecord {
+    date: DateTime<Utc>,
+    client_id: String,
+    method: String,
+    template: String,
+    // 0 when the request failed without a response
+    status: u16,
+    duration: Option<f64>,
+    request_size: usize,
+    response_size: usize,
+}
+
+// Responses per status class, `failed` counting requests without a status
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusClasses {
+    pub informational: usize,
+    pub success: usize,
+    pub redirect: usize,
+    pub client_error: usize,
+    pub server_error: usize,
+    pub failed: usize,
+}
+
+impl StatusClasses {
+    fn count(&mut self, status: u16) {
+        match status {
+            100..=199 => self.informational += 1,
+            200..=299 => self.success += 1,
+            300..=399 => self.redirect += 1,
+            400..=499 => self.client_error += 1,
+            500..=599 => self.server_error += 1,
+            _ => self.failed += 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointStats {
+    pub method: String,
+    // URL without query, numeric and UUID segments replaced by `:id` and `:uuid`
+    pub path: String,
+    pub count: usize,
+    pub statuses: StatusClasses,
+    // Share of responses in each class, 0 to 1
+    pub client_error_rate: f64,
+    pub server_error_rate: f64,
+    pub failure_rate: f64,
+    // Latency in ms
+    pub latency: Distribution,
+    // Serialized sizes in bytes
+    pub request_size: Distribution,
+    pub response_size: Distribution,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiAnalytics {
+    // Oldest and newest response taken into account
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub endpoints: Vec<EndpointStats>,
+}
+
+static API_RECORDS: OnceLock<Mutex<VecDeque<ApiRecord>>> = OnceLock::new();
+
+fn get_api_records() -> &'static Mutex<VecDeque<ApiRecord>> {
+    API_RECORDS.get_or_init(|| Mutex::new(VecDeque::new()))
+}
+
+fn is_uuid(segment: &str) -> bool {
+    let groups: Vec<&str> = segment.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(group, len)| group.len() == len && group.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+// Collapse the ids of a URL so requests to the same endpoint group together:
+// `https://api.test/users/42?full=1` becomes `https://api.test/users/:id`
+fn template_path(url: &str) -> String {
+    let url = url.split(['?', '#']).next().unwrap_or("");
+    let (origin, path) = match url.find("://") {
+        Some(scheme_end) => {
+            let host_start = scheme_end + 3;
+            match url[host_start..].find('/') {
+                Some(path_start) => url.split_at(host_start + path_start),
+                None => (url, ""),
+            }
+        }
+        None => ("", url),
+    };
+
+    let path: Vec<&str> = path
+        .split('/')
+        .map(|segment| {
+            if !segment.is_empty() && segment.chars().all(|c| c.is_ascii_digit()) {
+                ":id"
+            } else if is_uuid(segment) {
+                ":uuid"
+            } else {
+                segment
+            }
+        })
+        .collect();
+    format!("{}{}", origin, path.join("/"))
+}
+
+// Size of a request or response body, strings counted as sent rather than as JSON
+fn body_size(body: Option<&Value>) -> usize {
+    match body {
+        None | Some(Value::Null) => 0,
+        Some(Value::String(text)) => text.len(),
+        Some(value) => json_size(value),
+    }
+}
+
+// Note the outcome of an `api.response` command
+pub fn observe(cmd: &Command) {
+    if cmd.r#type != "api.response" {
+        return;
+    }
+    let Some(client_id) = &cmd.client_id else {
+        return;
+    };
+    let request = cmd.payload.get("request");
+    let response = cmd.payload.get("response");
+    let Some(url) = request.and_then(|r| r.get("url")).and_then(|u| u.as_str()) else {
+        return;
+    };
+    // Endpoints are shown in the UI, so rules matching the URL apply to them
+    let shown = redaction::redact_copy(&cmd.r#type, &serde_json::json!({ "request": { "url": url } }));
+    let url = shown["request"]["url"].as_str().unwrap_or(url);
+
+    let record = ApiRecord {
+        date: Utc::now(),
+        client_id: client_id.clone(),
+        method: request
+            .and_then(|r| r.get("method"))
+            .and_then(|m| m.as_str())
+            .unwrap_or("GET")
+            .to_uppercase(),
+        template: template_path(url),
+        status: response
+            .and_then(|r| r.get("status"))
+            .and_then(|s| s.as_u64())
+            .and_then(|s| u16::try_from(s).ok())
+            .unwrap_or(0),
+        duration: cmd.payload.get("duration").and_then(|d| d.as_f64()),
+        request_size: body_size(request.and_then(|r| r.get("data"))),
+        response_size: body_size(response.and_then(|r| r.get("body"))),
+    };
+
+    let mut records = get_api_records().lock().unwrap();
+    records.push_back(record);
+    if records.len() > MAX_RECORDS {
+        records.pop_front();
+    }
+}
+
+pub fn clear() {
+    get_api_records().lock().unwrap().clear();
+}
+
+fn rate(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64
+    }
+}
+
+// Start of a window ending now, None when it reaches further back than dates
+// go, in which case every response is in it
+fn window_start(now: DateTime<Utc>, window_ms: u64) -> Option<DateTime<Utc>> {
+    let window = chrono::Duration::try_milliseconds(i64::try_from(window_ms).ok()?)?;
+    now.checked_sub_signed(window)
+}
+
+// Per-endpoint statistics of the responses received in the last `window_ms`,
+// or of every response kept when no window is given
+#[tauri::command]
+pub fn get_api_analytics(client_id: Option<String>, window_ms: Option<u64>) -> ApiAnalytics {
+    let since = window_ms.and_then(|ms| window_start(Utc::now(), ms));
+    let records = get_api_records().lock().unwrap();
+    let selected: Vec<&ApiRecord> = records
+        .iter()
+        .filter(|r| since.is_none_or(|since| r.date >= since))
+        .filter(|r| client_id.as_ref().is_none_or(|id| *id == r.client_id))
+        .collect();
+
+    let mut groups: HashMap<(&str, &str), Vec<&ApiRecord>> = HashMap::new();
+    for record in &selected {
+        groups
+            .entry((record.method.as_str(), record.template.as_str()))
+            .or_default()
+            .push(record);
+    }
+
+    let mut endpoints: Vec<EndpointStats> = groups
+        .into_iter()
+        .map(|((method, path), records)| {
+            let mut statuses = StatusClasses::default();
+            for record in &records {
+                statuses.count(record.status);
+            }
+            let count = records.len();
+            EndpointStats {
+                method: method.to_string(),
+                path: path.to_string(),
+                count,
+                client_error_rate: rate(statuses.client_error, count),
+                server_error_rate: rate(statuses.server_error, count),
+                failure_rate: rate(statuses.failed, count),
+                statuses,
+                latency: Distribution::from_values(records.iter().filter_map(|r| r.duration).collect()),
+                request_size: Distribution::from_values(records.iter().map(|r| r.request_size as f64).collect()),
+                response_size: Distribution::from_values(records.iter().map(|r| r.response_size as f64).collect()),
+            }
+        })
+        .collect();
+    endpoints.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.path.cmp(&b.path)));
+
+    ApiAnalytics {
+        from: selected.first().map(|r| r.date.to_rfc3339()),
+        to: selected.last().map(|r| r.date.to_rfc3339()),
+        endpoints,
+    }
+}
+
+#[tauri::command]
+pub fn clear_api_analytics() {
+    clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn template_path_collapses_ids() {
+        assert_eq!(template_path("https://api.test/users/42?full=1"), "https://api.test/users/:id");
+        assert_eq!(
+            template_path("https://api.test/orders/3f2b8c1e-9d4a-4b6f-8e2a-1c5d7f9b0a3e/items/7#top"),
+            "https://api.test/orders/:uuid/items/:id"
+        );
+        assert_eq!(template_path("/v2/users/42/"), "/v2/users/:id/");
+        assert_eq!(template_path("https://api.test"), "https://api.test");
+        assert_eq!(template_path("https://api.test/users/me"), "https://api.test/users/me");
+        assert_eq!(template_path("https://api.test/v2/users/a42"), "https://api.test/v2/users/a42");
+    }
+
+    #[test]
+    fn window_start_handles_huge_windows() {
+        let now = Utc::now();
+        assert_eq!(window_start(now, 1000), Some(now - chrono::Duration::seconds(1)));
+        assert_eq!(window_start(now, u64::MAX), None);
+        assert_eq!(window_start(now, i64::MAX as u64), None);
+    }
+}
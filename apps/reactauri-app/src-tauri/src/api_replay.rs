@@ -0,0 +1,205 @@
+use crate::reactauri_core_server::{self, Command};
+use crate::request_snippets::{self, ApiRequest, Body};
+use crate::state_diff::{self, StateDiff};
+use log::info;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Addresses emulators use to reach the host machine, which is where the
+// replay runs from
+const HOST_ALIASES: [&str; 2] = ["10.0.2.2", "10.0.3.2"];
+
+// Changes applied to the captured request before it is sent again
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiReplayOptions {
+    pub url: Option<String>,
+    pub method: Option<String>,
+    // Headers to set, a null value removes the header
+    #[serde(default)]
+    pub headers: Map<String, Value>,
+    // Replaces the body, an empty string sends none
+    pub body: Option<Value>,
+    pub timeout_ms: Option<u64>,
+    // For local servers with self-signed certificates
+    #[serde(default)]
+    pub accept_invalid_certs: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiReplayResult {
+    // The `api.response` payload added to the timeline
+    pub payload: Value,
+    // From the original response to the new one
+    pub diff: StateDiff,
+}
+
+fn apply_options(request: &mut ApiRequest, options: &ApiReplayOptions) {
+    if let Some(url) = &options.url {
+        request.url = url.clone();
+    }
+    if let Some(method) = &options.method {
+        request.method = method.to_uppercase();
+    }
+    for (name, value) in &options.headers {
+        request.headers.retain(|(header, _)| !header.eq_ignore_ascii_case(name));
+        match value {
+            Value::Null => {}
+            Value::String(value) => request.headers.push((name.clone(), value.clone())),
+            other => request.headers.push((name.clone(), other.to_string())),
+        }
+    }
+    if let Some(body) = &options.body {
+        request.body = match body {
+            Value::String(text) if text.is_empty() => None,
+            Value::String(text) => Some(Body::Text(text.clone())),
+            value => Some(Body::Json(value.clone())),
+        };
+        if matches!(request.body, Some(Body::Json(_)))
+            && !request.headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+        {
+            request.headers.push(("Content-Type".to_string(), "application/json".to_string()));
+        }
+    }
+}
+
+fn resolve_url(url: &str) -> Result<Url, String> {
+    let mut url = Url::parse(url).map_err(|e| format!("Invalid URL {}: {}", url, e))?;
+    if url.host_str().is_some_and(|host| HOST_ALIASES.contains(&host)) {
+        let _ = url.set_host(Some("127.0.0.1"));
+    }
+    Ok(url)
+}
+
+fn response_body(bytes: &[u8]) -> Value {
+    if bytes.is_empty() {
+        return Value::Null;
+    }
+    serde_json::from_slice(bytes).unwrap_or_else(|_| Value::String(String::from_utf8_lossy(bytes).to_string()))
+}
+
+// Send a captured request again and add the response to the timeline as a
+// new `api.response`, marked as replayed. `original` is the payload as the
+// client sent it, `shown` as it is on the timeline, after redaction.
+pub async fn replay(
+    app_handle: &AppHandle,
+    client_id: Option<String>,
+    original: &Value,
+    shown: &Value,
+    options: &ApiReplayOptions,
+) -> Result<ApiReplayResult, String> {
+    let mut request = ApiRequest::from_payload(original)?;
+    apply_options(&mut request, options);
+    let url = resolve_url(&request.url)?;
+    let method = reqwest::Method::from_bytes(request.method.as_bytes())
+        .map_err(|_| format!("Invalid method {}", request.method))?;
+
+    let mut builder = reqwest::Client::builder()
+        .timeout(options.timeout_ms.map(Duration::from_millis).unwrap_or(DEFAULT_TIMEOUT))
+        .danger_accept_invalid_certs(options.accept_invalid_certs);
+    // System proxies would not reach servers on this machine
+    if url.host_str().is_some_and(|host| host == "localhost" || host.starts_with("127.") || host == "[::1]") {
+        builder = builder.no_proxy();
+    }
+    let client = builder.build().map_err(|e| e.to_string())?;
+
+    let mut http_request = client.request(method, url.clone());
+    for (name, value) in &request.headers {
+        http_request = http_request.header(name, value);
+    }
+    if let Some(body) = request.body_text() {
+        http_request = http_request.body(body);
+    }
+
+    info!("Replaying {} {}", request.method, url);
+    let started = Instant::now();
+    let response = http_request
+        .send()
+        .await
+        .map_err(|e| format!("Request to {} failed: {}", url, e))?;
+    let status = response.status().as_u16();
+    let headers: Map<String, Value> = response
+        .headers()
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                Value::String(String::from_utf8_lossy(value.as_bytes()).to_string()),
+            )
+        })
+        .collect();
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to read the response of {}: {}", url, e))?;
+    let duration = started.elapsed().as_millis() as u64;
+
+    let new_response = serde_json::json!({
+        "status": status,
+        "headers": headers,
+        "body": response_body(&bytes),
+    });
+    let payload = serde_json::json!({
+        "request": {
+            "url": request.url,
+            "method": request.method,
+            "headers": request
+                .headers
+                .iter()
+                .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+                .collect::<Map<String, Value>>(),
+            "data": match &request.body {
+                Some(Body::Json(value)) => value.clone(),
+                Some(Body::Text(text)) => Value::String(text.clone()),
+                None => Value::Null,
+            },
+        },
+        "response": new_response,
+        "duration": duration,
+        "replayed": true,
+    });
+
+    let cmd = Command {
+        r#type: "api.response".to_string(),
+        payload,
+        important: Some(Value::Bool(!(200..300).contains(&status))),
+        connection_id: None,
+        message_id: None,
+        date: Some(chrono::Utc::now().to_rfc3339()),
+        delta_time: Some(Value::from(0)),
+        client_id,
+        payload_handle: None,
+        aggregated_count: None,
+        validation_warnings: None,
+        redaction: None,
+    };
+    let payload = reactauri_core_server::emit_server_command(app_handle, cmd).await;
+    let diff = state_diff::diff(
+        shown.get("response").unwrap_or(&Value::Null),
+        payload.get("response").unwrap_or(&Value::Null),
+    );
+
+    Ok(ApiReplayResult { payload, diff })
+}
+
+// Replay the request of an `api.response`, given inline or by payload handle,
+// from its unredacted copy when redaction kept one
+#[tauri::command]
+pub async fn replay_api_request(
+    app: AppHandle,
+    client_id: Option<String>,
+    payload: Option<Value>,
+    payload_handle: Option<String>,
+    raw_payload_handle: Option<String>,
+    options: Option<ApiReplayOptions>,
+) -> Result<ApiReplayResult, String> {
+    let shown = request_snippets::resolve_payload(payload, payload_handle)?;
+    let original = request_snippets::resolve_raw_payload(raw_payload_handle, shown.clone());
+    replay(&app, client_id, &original, &shown, &options.unwrap_or_default()).await
+}
@@ -0,0 +1,274 @@
+use crate::reactauri_core_server::Command;
+use crate::stats::Distribution;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+
+// Durations kept per step, the oldest are dropped first
+const MAX_SAMPLES: usize = 1000;
+// Step holding the duration of whole runs
+const TOTAL_STEP: &str = "(total)";
+const UNKNOWN_VERSION: &str = "unknown";
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sample {
+    pub date: String,
+    pub ms: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SeriesKey {
+    client_id: String,
+    app_version: String,
+    title: String,
+    step: String,
+}
+
+struct Series {
+    // Position of the step in the benchmark, for display
+    order: usize,
+    samples: VecDeque<Sample>,
+}
+
+#[derive(Default)]
+struct Benchmarks {
+    // App version from each client's `client.intro`
+    versions: HashMap<String, String>,
+    series: HashMap<SeriesKey, Series>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StepStats {
+    pub step: String,
+    pub stats: Distribution,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BenchmarkStats {
+    pub client_id: String,
+    pub app_version: String,
+    pub title: String,
+    pub steps: Vec<StepStats>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StepComparison {
+    pub title: String,
+    pub step: String,
+    pub base: Option<Distribution>,
+    pub target: Option<Distribution>,
+    // Change of the mean and p95 from base to target, in percent
+    pub mean_change: Option<f64>,
+    pub p95_change: Option<f64>,
+}
+
+// (title, order, step) and the durations of the base and target versions
+type StepKey = (String, usize, String);
+type VersionDurations = (Vec<f64>, Vec<f64>);
+
+static BENCHMARKS: OnceLock<Mutex<Benchmarks>> = OnceLock::new();
+
+fn get_benchmarks() -> &'static Mutex<Benchmarks> {
+    BENCHMARKS.get_or_init(|| Mutex::new(Benchmarks::default()))
+}
+
+// Apps are expected to pass their version in the `client` options
+fn intro_version(payload: &Value) -> Option<String> {
+    ["appVersion", "version"]
+        .iter()
+        .find_map(|field| payload.get(*field))
+        .and_then(|v| match v {
+            Value::String(s) => Some(s.clone()),
+            Value::Number(n) => Some(n.to_string()),
+            _ => None,
+        })
+}
+
+fn push_sample(series: &mut HashMap<SeriesKey, Series>, key: SeriesKey, order: usize, sample: Sample) {
+    let series = series.entry(key).or_insert_with(|| Series {
+        order,
+        samples: VecDeque::new(),
+    });
+    series.order = order;
+    series.samples.push_back(sample);
+    if series.samples.len() > MAX_SAMPLES {
+        series.samples.pop_front();
+    }
+}
+
+// Aggregate `benchmark.report` commands, noting app versions from `client.intro`
+pub fn observe(cmd: &Command) {
+    let Some(client_id) = &cmd.client_id else {
+        return;
+    };
+    let mut benchmarks = get_benchmarks().lock().unwrap();
+    match cmd.r#type.as_str() {
+        "client.intro" => {
+            let version = intro_version(&cmd.payload).unwrap_or_else(|| UNKNOWN_VERSION.to_string());
+            benchmarks.versions.insert(client_id.clone(), version);
+        }
+        "benchmark.report" => {
+            let Some(title) = cmd.payload.get("title").and_then(|t| t.as_str()) else {
+                return;
+            };
+            let Some(steps) = cmd.payload.get("steps").and_then(|s| s.as_array()) else {
+                return;
+            };
+            let app_version = benchmarks
+                .versions
+                .get(client_id)
+                .cloned()
+                .unwrap_or_else(|| UNKNOWN_VERSION.to_string());
+            let date = chrono::Utc::now().to_rfc3339();
+            let key = |step: &str| SeriesKey {
+                client_id: client_id.clone(),
+                app_version: app_version.clone(),
+                title: title.to_string(),
+                step: step.to_string(),
+            };
+
+            // The first step marks the start of the run, the others carry the
+            // time since the previous one
+            for (order, step) in steps.iter().enumerate().skip(1) {
+                let Some(delta) = step.get("delta").and_then(|d| d.as_f64()) else {
+                    continue;
+                };
+                let step_title = step.get("title").and_then(|t| t.as_str()).unwrap_or("");
+                let sample = Sample {
+                    date: date.clone(),
+                    ms: delta,
+                };
+                push_sample(&mut benchmarks.series, key(step_title), order, sample);
+            }
+            if let Some(total) = steps.last().and_then(|s| s.get("time")).and_then(|t| t.as_f64()) {
+                let sample = Sample { date, ms: total };
+                push_sample(&mut benchmarks.series, key(TOTAL_STEP), usize::MAX, sample);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn distribution(samples: &VecDeque<Sample>) -> Distribution {
+    Distribution::from_values(samples.iter().map(|s| s.ms).collect())
+}
+
+fn percent_change(base: f64, target: f64) -> Option<f64> {
+    (base != 0.0).then(|| (target - base) / base * 100.0)
+}
+
+// Step statistics per client, app version and benchmark
+#[tauri::command]
+pub fn get_benchmark_stats(client_id: Option<String>, app_version: Option<String>) -> Vec<BenchmarkStats> {
+    let benchmarks = get_benchmarks().lock().unwrap();
+    let mut grouped: BTreeMap<(String, String, String), Vec<(usize, StepStats)>> = BTreeMap::new();
+    for (key, series) in &benchmarks.series {
+        if client_id.as_ref().is_some_and(|id| *id != key.client_id)
+            || app_version.as_ref().is_some_and(|v| *v != key.app_version)
+        {
+            continue;
+        }
+        grouped
+            .entry((key.client_id.clone(), key.app_version.clone(), key.title.clone()))
+            .or_default()
+            .push((
+                series.order,
+                StepStats {
+                    step: key.step.clone(),
+                    stats: distribution(&series.samples),
+                },
+            ));
+    }
+
+    grouped
+        .into_iter()
+        .map(|((client_id, app_version, title), mut steps)| {
+            steps.sort_by_key(|(order, _)| *order);
+            BenchmarkStats {
+                client_id,
+                app_version,
+                title,
+                steps: steps.into_iter().map(|(_, step)| step).collect(),
+            }
+        })
+        .collect()
+}
+
+// Durations of one step over time, oldest first
+#[tauri::command]
+pub fn get_benchmark_history(client_id: String, app_version: String, title: String, step: String) -> Vec<Sample> {
+    let benchmarks = get_benchmarks().lock().unwrap();
+    let key = SeriesKey {
+        client_id,
+        app_version,
+        title,
+        step,
+    };
+    benchmarks
+        .series
+        .get(&key)
+        .map(|series| series.samples.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+// Compare the steps of two app versions, across every client unless one is given
+#[tauri::command]
+pub fn compare_benchmark_versions(
+    base_version: String,
+    target_version: String,
+    client_id: Option<String>,
+    title: Option<String>,
+) -> Vec<StepComparison> {
+    let benchmarks = get_benchmarks().lock().unwrap();
+    let mut steps: BTreeMap<StepKey, VersionDurations> = BTreeMap::new();
+    for (key, series) in &benchmarks.series {
+        if client_id.as_ref().is_some_and(|id| *id != key.client_id)
+            || title.as_ref().is_some_and(|t| *t != key.title)
+        {
+            continue;
+        }
+        let values = series.samples.iter().map(|s| s.ms);
+        let entry = steps
+            .entry((key.title.clone(), series.order, key.step.clone()))
+            .or_default();
+        if key.app_version == base_version {
+            entry.0.extend(values);
+        } else if key.app_version == target_version {
+            entry.1.extend(values);
+        }
+    }
+
+    steps
+        .into_iter()
+        .filter(|(_, (base, target))| !base.is_empty() || !target.is_empty())
+        .map(|((title, _, step), (base, target))| {
+            let base = (!base.is_empty()).then(|| Distribution::from_values(base));
+            let target = (!target.is_empty()).then(|| Distribution::from_values(target));
+            let (mean_change, p95_change) = match (&base, &target) {
+                (Some(base), Some(target)) => (
+                    percent_change(base.mean, target.mean),
+                    percent_change(base.p95, target.p95),
+                ),
+                _ => (None, None),
+            };
+            StepComparison {
+                title,
+                step,
+                base,
+                target,
+                mean_change,
+                p95_change,
+            }
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn clear_benchmarks() {
+    get_benchmarks().lock().unwrap().series.clear();
+}
@@ -0,0 +1,212 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+// Command type used by clients to split a large message over several frames:
+// { "type": "chunk", "payload": { "chunkId": "...", "index": 0, "total": 3, "data": "..." } }
+// The `data` of all chunks, joined in index order, is the JSON text of one command.
+pub const CHUNK_TYPE: &str = "chunk";
+
+// Incomplete messages are discarded after this long without a new chunk
+const CHUNK_TIMEOUT: Duration = Duration::from_secs(60);
+// Limits on what a single connection may have in flight
+const MAX_PENDING_MESSAGES: usize = 16;
+const MAX_CHUNKS_PER_MESSAGE: usize = 100_000;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Chunk {
+    chunk_id: String,
+    index: usize,
+    total: usize,
+    data: String,
+}
+
+struct PendingMessage {
+    total: usize,
+    // Received chunks by index, so memory follows what was sent rather than
+    // the total a client announces
+    parts: BTreeMap<usize, String>,
+    size: usize,
+    last_chunk_at: Instant,
+}
+
+// Reassembles chunked messages for a single connection, owned by its receive loop.
+// `max_message_size` bounds every message in flight together, not each one.
+pub struct ChunkAssembler {
+    pending: HashMap<String, PendingMessage>,
+    max_message_size: usize,
+}
+
+impl ChunkAssembler {
+    pub fn new(max_message_size: usize) -> Self {
+        Self {
+            pending: HashMap::new(),
+            max_message_size,
+        }
+    }
+
+    // Add a chunk, returning the full message text once every chunk has arrived
+    pub fn push(&mut self, payload: &serde_json::Value) -> Result<Option<String>, String> {
+        let chunk = Chunk::deserialize(payload).map_err(|e| format!("Invalid chunk: {}", e))?;
+
+        self.pending
+            .retain(|_, message| message.last_chunk_at.elapsed() < CHUNK_TIMEOUT);
+
+        if chunk.total == 0 || chunk.total > MAX_CHUNKS_PER_MESSAGE {
+            return Err(format!("Invalid chunk total {} for {}", chunk.total, chunk.chunk_id));
+        }
+        if chunk.index >= chunk.total {
+            return Err(format!(
+                "Chunk index {} out of range for {} ({} chunks)",
+                chunk.index, chunk.chunk_id, chunk.total
+            ));
+        }
+        if !self.pending.contains_key(&chunk.chunk_id) && self.pending.len() >= MAX_PENDING_MESSAGES {
+            return Err(format!("Too many chunked messages in flight, dropping {}", chunk.chunk_id));
+        }
+
+        let message = self
+            .pending
+            .entry(chunk.chunk_id.clone())
+            .or_insert_with(|| PendingMessage {
+                total: chunk.total,
+                parts: BTreeMap::new(),
+                size: 0,
+                last_chunk_at: Instant::now(),
+            });
+
+        if message.total != chunk.total {
+            self.pending.remove(&chunk.chunk_id);
+            return Err(format!("Chunk total changed for {}", chunk.chunk_id));
+        }
+
+        message.last_chunk_at = Instant::now();
+        message.size += chunk.data.len();
+        if let Some(previous) = message.parts.insert(chunk.index, chunk.data) {
+            message.size -= previous.len();
+        }
+
+        if message.size > self.max_message_size {
+            self.pending.remove(&chunk.chunk_id);
+            return Err(format!(
+                "Chunked message {} exceeds the maximum size of {} bytes",
+                chunk.chunk_id, self.max_message_size
+            ));
+        }
+        let complete = message.parts.len() == message.total;
+
+        let pending_size: usize = self.pending.values().map(|message| message.size).sum();
+        if pending_size > self.max_message_size {
+            self.pending.remove(&chunk.chunk_id);
+            return Err(format!(
+                "Chunked messages in flight exceed {} bytes, dropping {}",
+                self.max_message_size, chunk.chunk_id
+            ));
+        }
+
+        if !complete {
+            return Ok(None);
+        }
+
+        let message = self.pending.remove(&chunk.chunk_id).unwrap();
+        let mut text = String::with_capacity(message.size);
+        for part in message.parts.into_values() {
+            text.push_str(&part);
+        }
+        Ok(Some(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn chunk(id: &str, index: usize, total: usize, data: &str) -> serde_json::Value {
+        json!({ "chunkId": id, "index": index, "total": total, "data": data })
+    }
+
+    #[test]
+    fn reassembles_chunks_in_index_order() {
+        let mut assembler = ChunkAssembler::new(1024);
+
+        assert_eq!(assembler.push(&chunk("a", 2, 3, "}}")), Ok(None));
+        assert_eq!(assembler.push(&chunk("a", 0, 3, r#"{"type":"log","#)), Ok(None));
+        // A chunk sent again replaces the first copy
+        assert_eq!(assembler.push(&chunk("a", 2, 3, "}}")), Ok(None));
+        assert_eq!(
+            assembler.push(&chunk("a", 1, 3, r#""payload":{"message":"hi""#)),
+            Ok(Some(r#"{"type":"log","payload":{"message":"hi"}}"#.to_string()))
+        );
+        assert!(assembler.pending.is_empty());
+    }
+
+    #[test]
+    fn keeps_messages_apart() {
+        let mut assembler = ChunkAssembler::new(1024);
+
+        assert_eq!(assembler.push(&chunk("a", 0, 2, "a0")), Ok(None));
+        assert_eq!(assembler.push(&chunk("b", 0, 2, "b0")), Ok(None));
+        assert_eq!(assembler.push(&chunk("b", 1, 2, "b1")), Ok(Some("b0b1".to_string())));
+        assert_eq!(assembler.push(&chunk("a", 1, 2, "a1")), Ok(Some("a0a1".to_string())));
+    }
+
+    #[test]
+    fn rejects_invalid_chunks() {
+        let mut assembler = ChunkAssembler::new(1024);
+
+        assert!(assembler.push(&json!({ "chunkId": "a" })).is_err());
+        assert!(assembler.push(&chunk("a", 0, 0, "")).is_err());
+        assert!(assembler.push(&chunk("a", 3, 3, "")).is_err());
+        assert!(assembler.push(&chunk("a", 0, MAX_CHUNKS_PER_MESSAGE + 1, "")).is_err());
+
+        assert_eq!(assembler.push(&chunk("a", 0, 3, "x")), Ok(None));
+        assert!(assembler.push(&chunk("a", 1, 4, "x")).is_err());
+        assert!(assembler.pending.is_empty());
+    }
+
+    #[test]
+    fn drops_a_message_over_the_size_limit() {
+        let mut assembler = ChunkAssembler::new(8);
+
+        assert_eq!(assembler.push(&chunk("a", 0, 3, "1234")), Ok(None));
+        assert!(assembler.push(&chunk("a", 1, 3, "56789")).is_err());
+        assert!(assembler.pending.is_empty());
+    }
+
+    #[test]
+    fn limits_the_bytes_of_all_messages_in_flight() {
+        let mut assembler = ChunkAssembler::new(8);
+
+        assert_eq!(assembler.push(&chunk("a", 0, 2, "1234")), Ok(None));
+        assert_eq!(assembler.push(&chunk("b", 0, 2, "1234")), Ok(None));
+        // Each message is under the limit, but not both together
+        assert!(assembler.push(&chunk("c", 0, 2, "1")).is_err());
+        assert!(!assembler.pending.contains_key("c"));
+
+        assert_eq!(assembler.push(&chunk("a", 1, 2, "")), Ok(Some("1234".to_string())));
+        assert_eq!(assembler.push(&chunk("c", 0, 2, "1")), Ok(None));
+    }
+
+    #[test]
+    fn limits_the_number_of_messages_in_flight() {
+        let mut assembler = ChunkAssembler::new(1024);
+
+        for i in 0..MAX_PENDING_MESSAGES {
+            assert_eq!(assembler.push(&chunk(&i.to_string(), 0, 2, "x")), Ok(None));
+        }
+        assert!(assembler.push(&chunk("one more", 0, 2, "x")).is_err());
+        assert_eq!(assembler.push(&chunk("0", 1, 2, "y")), Ok(Some("xy".to_string())));
+    }
+
+    #[test]
+    fn allocates_for_the_chunks_received_not_the_total_announced() {
+        let mut assembler = ChunkAssembler::new(1024);
+
+        assert_eq!(assembler.push(&chunk("a", 0, MAX_CHUNKS_PER_MESSAGE, "x")), Ok(None));
+        assert_eq!(assembler.push(&chunk("a", MAX_CHUNKS_PER_MESSAGE - 1, MAX_CHUNKS_PER_MESSAGE, "y")), Ok(None));
+        assert_eq!(assembler.pending["a"].parts.len(), 2);
+        assert_eq!(assembler.pending["a"].size, 2);
+    }
+}
@@ -0,0 +1,412 @@
+use crate::reactauri_core_server::{self, Command, CommandWithClientId};
+use crate::redaction;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+use tokio::sync::oneshot;
+
+// How long to wait for a client to answer when no timeout is given
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Field of the response payload that must have a given value. `fields` are
+// JSON pointers tried in order, the first string found is compared, and none
+// found counts as "" (the root path is sent as either missing or null).
+struct ResponseMatch {
+    fields: &'static [&'static str],
+    value: String,
+}
+
+// Where a path request and its response carry the path
+const PATH_FIELDS: &[&str] = &["/path"];
+
+// A request waiting for its response. Consumed responses are handed to the
+// caller only, observed ones also go on to the timeline. Requests sent from
+// the timeline have no sender, they only hold their place in the queue so
+// their answer is not taken by a request sent after them.
+struct PendingRequest {
+    id: u64,
+    matcher: Option<ResponseMatch>,
+    consume: bool,
+    sender: Option<oneshot::Sender<Value>>,
+    sent_at: Instant,
+}
+
+type PendingKey = (String, String);
+
+static PENDING_REQUESTS: OnceLock<Mutex<HashMap<PendingKey, Vec<PendingRequest>>>> = OnceLock::new();
+static NEXT_REQUEST_ID: AtomicU64 = AtomicU64::new(1);
+
+fn get_pending_requests() -> &'static Mutex<HashMap<PendingKey, Vec<PendingRequest>>> {
+    PENDING_REQUESTS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+fn normalize_path(path: Option<&str>) -> &str {
+    path.unwrap_or("")
+}
+
+fn match_value<'a>(payload: &'a Value, fields: &[&str]) -> &'a str {
+    normalize_path(fields.iter().find_map(|field| payload.pointer(field).and_then(|v| v.as_str())))
+}
+
+fn remove_pending(key: &PendingKey, id: u64) {
+    let mut pending = get_pending_requests().lock().unwrap();
+    if let Some(requests) = pending.get_mut(key) {
+        requests.retain(|request| request.id != id);
+        if requests.is_empty() {
+            pending.remove(key);
+        }
+    }
+}
+
+fn enqueue(
+    key: PendingKey,
+    matcher: Option<ResponseMatch>,
+    consume: bool,
+    sender: Option<oneshot::Sender<Value>>,
+) -> u64 {
+    let id = NEXT_REQUEST_ID.fetch_add(1, Ordering::Relaxed);
+    get_pending_requests()
+        .lock()
+        .unwrap()
+        .entry(key)
+        .or_default()
+        .push(PendingRequest {
+            id,
+            matcher,
+            consume,
+            sender,
+            sent_at: Instant::now(),
+        });
+    id
+}
+
+// Who a received command answers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    // Nobody asked for it
+    Unrequested,
+    // A request sent from the timeline
+    Timeline,
+    // A request that also leaves the command on the timeline
+    Observed,
+    // A request that takes the command, which is not shown on the timeline
+    Consumed,
+}
+
+// Hand a received command to the oldest request waiting for it
+pub fn resolve(cmd: &Command) -> Resolution {
+    let Some(client_id) = &cmd.client_id else {
+        return Resolution::Unrequested;
+    };
+    let key = (client_id.clone(), cmd.r#type.clone());
+    let mut pending = get_pending_requests().lock().unwrap();
+    let Some(requests) = pending.get_mut(&key) else {
+        return Resolution::Unrequested;
+    };
+    // Timeline requests the client never answered
+    requests.retain(|request| request.sender.is_some() || request.sent_at.elapsed() < DEFAULT_TIMEOUT);
+
+    let Some(index) = requests.iter().position(|request| {
+        request.matcher.as_ref().is_none_or(|matcher| {
+            match_value(&cmd.payload, matcher.fields) == matcher.value
+        })
+    }) else {
+        return Resolution::Unrequested;
+    };
+
+    let request = requests.remove(index);
+    if requests.is_empty() {
+        pending.remove(&key);
+    }
+    let Some(sender) = request.sender else {
+        return Resolution::Timeline;
+    };
+    match sender.send(cmd.payload.clone()) {
+        Ok(()) if request.consume => Resolution::Consumed,
+        Ok(()) => Resolution::Observed,
+        // The caller gave up waiting
+        Err(_) => Resolution::Unrequested,
+    }
+}
+
+// Send a command to a client and wait for its `response_type` answer, with
+// a `path` matching the one given when set
+pub async fn request(
+    app_handle: AppHandle,
+    client_id: &str,
+    request_type: &str,
+    payload: Value,
+    response_type: &str,
+    path: Option<&str>,
+    timeout: Duration,
+) -> Result<Value, String> {
+    let matcher = path.map(|path| ResponseMatch {
+        fields: PATH_FIELDS,
+        value: normalize_path(Some(path)).to_string(),
+    });
+    send_and_wait(app_handle, client_id, request_type, payload, response_type, matcher, true, timeout).await
+}
+
+// Send a command to a client and wait until it reports a `response_type`
+// command whose first present `fields` pointer is `value`, leaving that
+// command on the timeline
+#[allow(clippy::too_many_arguments)]
+pub async fn send_and_observe(
+    app_handle: AppHandle,
+    client_id: &str,
+    request_type: &str,
+    payload: Value,
+    response_type: &str,
+    fields: &'static [&'static str],
+    value: &str,
+    timeout: Duration,
+) -> Result<Value, String> {
+    let matcher = Some(ResponseMatch {
+        fields,
+        value: value.to_string(),
+    });
+    send_and_wait(app_handle, client_id, request_type, payload, response_type, matcher, false, timeout).await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn send_and_wait(
+    app_handle: AppHandle,
+    client_id: &str,
+    request_type: &str,
+    payload: Value,
+    response_type: &str,
+    matcher: Option<ResponseMatch>,
+    consume: bool,
+    timeout: Duration,
+) -> Result<Value, String> {
+    if client_id.is_empty() || !reactauri_core_server::is_client_connected(client_id).await {
+        return Err(format!("Client {} is not connected", client_id));
+    }
+
+    let key = (client_id.to_string(), response_type.to_string());
+    let (sender, receiver) = oneshot::channel();
+    let id = enqueue(key.clone(), matcher, consume, Some(sender));
+
+    let command = CommandWithClientId {
+        r#type: request_type.to_string(),
+        payload,
+        client_id: client_id.to_string(),
+        important: false,
+        date: Some(chrono::Utc::now().to_rfc3339()),
+        delta_time: Some(0),
+    };
+    reactauri_core_server::send_command(app_handle, command).await;
+
+    match tokio::time::timeout(timeout, receiver).await {
+        Ok(Ok(response)) => Ok(response),
+        Ok(Err(_)) => Err(format!("Request {} to client {} was cancelled", request_type, client_id)),
+        Err(_) => {
+            remove_pending(&key, id);
+            Err(format!(
+                "Client {} did not answer {} within {} ms",
+                client_id,
+                request_type,
+                timeout.as_millis()
+            ))
+        }
+    }
+}
+
+// The response to a request the timeline can send, and the payload fields
+// the response echoes back, if any
+fn response_for(request_type: &str) -> Option<(&'static str, Option<&'static [&'static str]>)> {
+    match request_type {
+        "state.values.request" => Some(("state.values.response", Some(PATH_FIELDS))),
+        "state.keys.request" => Some(("state.keys.response", Some(PATH_FIELDS))),
+        "state.backup.request" => Some(("state.backup.response", None)),
+        "repl.ls" => Some(("repl.ls.response", None)),
+        "repl.execute" => Some(("repl.execute.response", None)),
+        _ => None,
+    }
+}
+
+// Queue a request sent from the timeline behind the ones already waiting, so
+// its answer goes to the timeline rather than to a later server request
+pub fn track_sent(client_id: &str, request_type: &str, payload: &Value) {
+    if client_id.is_empty() {
+        return;
+    }
+    let Some((response_type, fields)) = response_for(request_type) else {
+        return;
+    };
+    let matcher = fields.map(|fields| ResponseMatch {
+        fields,
+        value: match_value(payload, fields).to_string(),
+    });
+    enqueue((client_id.to_string(), response_type.to_string()), matcher, false, None);
+}
+
+// Drop the requests waiting on a client that went away, failing them right away
+pub fn cancel_client(client_id: &str) {
+    get_pending_requests()
+        .lock()
+        .unwrap()
+        .retain(|(pending_client_id, _), _| pending_client_id != client_id);
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateValuesResponse {
+    pub path: Option<String>,
+    #[serde(default)]
+    pub value: Value,
+    pub valid: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateKeysResponse {
+    pub path: Option<String>,
+    #[serde(default)]
+    pub keys: Option<Vec<String>>,
+    pub valid: bool,
+}
+
+fn timeout_or_default(timeout_ms: Option<u64>) -> Duration {
+    timeout_ms.map(Duration::from_millis).unwrap_or(DEFAULT_TIMEOUT)
+}
+
+// An answer as the UI gets it, with the redaction rules for `response_type`
+// applied. Answers are kept as sent for requests that feed data back to apps.
+pub fn redacted<T: Serialize + DeserializeOwned>(response_type: &str, response: T) -> Result<T, String> {
+    let payload = serde_json::to_value(&response).map_err(|e| e.to_string())?;
+    serde_json::from_value(redaction::redact_copy(response_type, &payload))
+        .map_err(|e| format!("Invalid {} once redacted: {}", response_type, e))
+}
+
+pub async fn request_state_values_from(
+    app_handle: AppHandle,
+    client_id: &str,
+    path: Option<String>,
+    timeout: Duration,
+) -> Result<StateValuesResponse, String> {
+    let payload = request(
+        app_handle,
+        client_id,
+        "state.values.request",
+        serde_json::json!({ "path": path }),
+        "state.values.response",
+        Some(path.as_deref().unwrap_or("")),
+        timeout,
+    )
+    .await?;
+    serde_json::from_value(payload).map_err(|e| format!("Invalid state.values.response: {}", e))
+}
+
+pub async fn request_state_keys_from(
+    app_handle: AppHandle,
+    client_id: &str,
+    path: Option<String>,
+    timeout: Duration,
+) -> Result<StateKeysResponse, String> {
+    let payload = request(
+        app_handle,
+        client_id,
+        "state.keys.request",
+        serde_json::json!({ "path": path }),
+        "state.keys.response",
+        Some(path.as_deref().unwrap_or("")),
+        timeout,
+    )
+    .await?;
+    serde_json::from_value(payload).map_err(|e| format!("Invalid state.keys.response: {}", e))
+}
+
+#[tauri::command]
+pub async fn request_state_values(
+    app: AppHandle,
+    client_id: String,
+    path: Option<String>,
+    timeout_ms: Option<u64>,
+) -> Result<StateValuesResponse, String> {
+    let response = request_state_values_from(app, &client_id, path, timeout_or_default(timeout_ms)).await?;
+    redacted("state.values.response", response)
+}
+
+#[tauri::command]
+pub async fn request_state_keys(
+    app: AppHandle,
+    client_id: String,
+    path: Option<String>,
+    timeout_ms: Option<u64>,
+) -> Result<StateKeysResponse, String> {
+    let response = request_state_keys_from(app, &client_id, path, timeout_or_default(timeout_ms)).await?;
+    redacted("state.keys.response", response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn response(client_id: &str, command_type: &str, payload: Value) -> Command {
+        serde_json::from_value(json!({ "type": command_type, "payload": payload, "clientId": client_id })).unwrap()
+    }
+
+    fn wait_for(client_id: &str, response_type: &str, path: Option<&str>) -> oneshot::Receiver<Value> {
+        let (sender, receiver) = oneshot::channel();
+        let matcher = path.map(|path| ResponseMatch {
+            fields: PATH_FIELDS,
+            value: path.to_string(),
+        });
+        enqueue((client_id.to_string(), response_type.to_string()), matcher, true, Some(sender));
+        receiver
+    }
+
+    #[test]
+    fn consumes_the_answer_to_a_server_request() {
+        let mut receiver = wait_for("server-only", "state.backup.response", None);
+        assert_eq!(Resolution::Consumed, resolve(&response("server-only", "state.backup.response", json!({ "state": { "a": 1 } }))));
+        assert_eq!(receiver.try_recv().unwrap(), json!({ "state": { "a": 1 } }));
+        assert_eq!(Resolution::Unrequested, resolve(&response("server-only", "state.backup.response", json!({ "state": {} }))));
+    }
+
+    #[test]
+    fn leaves_answers_to_timeline_requests_alone() {
+        track_sent("shared", "state.backup.request", &json!(null));
+        let mut receiver = wait_for("shared", "state.backup.response", None);
+
+        assert_eq!(Resolution::Timeline, resolve(&response("shared", "state.backup.response", json!({ "state": { "ui": true } }))));
+        assert!(receiver.try_recv().is_err());
+        assert_eq!(Resolution::Consumed, resolve(&response("shared", "state.backup.response", json!({ "state": { "server": true } }))));
+        assert_eq!(receiver.try_recv().unwrap(), json!({ "state": { "server": true } }));
+    }
+
+    #[test]
+    fn matches_responses_by_path() {
+        let mut receiver = wait_for("paths", "state.values.response", Some("user"));
+        track_sent("paths", "state.values.request", &json!({ "path": "settings" }));
+
+        let settings = json!({ "path": "settings", "value": 1, "valid": true });
+        assert_eq!(Resolution::Timeline, resolve(&response("paths", "state.values.response", settings)));
+        let user = json!({ "path": "user", "value": 2, "valid": true });
+        assert_eq!(Resolution::Consumed, resolve(&response("paths", "state.values.response", user.clone())));
+        assert_eq!(receiver.try_recv().unwrap(), user);
+    }
+
+    #[test]
+    fn matches_on_the_first_field_present() {
+        let (sender, mut receiver) = oneshot::channel();
+        let matcher = Some(ResponseMatch {
+            fields: &["/name", "/action/type"],
+            value: "login/done".to_string(),
+        });
+        enqueue(("fields".to_string(), "state.action.complete".to_string()), matcher, false, Some(sender));
+
+        let named = json!({ "name": "other", "action": { "type": "login/done" } });
+        assert_eq!(Resolution::Unrequested, resolve(&response("fields", "state.action.complete", named)));
+        let unnamed = json!({ "action": { "type": "login/done" } });
+        assert_eq!(Resolution::Observed, resolve(&response("fields", "state.action.complete", unnamed.clone())));
+        assert_eq!(receiver.try_recv().unwrap(), unnamed);
+    }
+}
@@ -0,0 +1,87 @@
+use crate::reactauri_core_server::Command;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::async_runtime;
+use tauri::AppHandle;
+use tauri::Emitter;
+use tokio::time::interval;
+
+// Coalesces commands sent to the webview. While traffic is low every command is
+// emitted right away as a `command` event; once commands arrive faster than the
+// flush interval they are buffered and emitted together as a `commands` event.
+struct CommandBatcher {
+    pending: Vec<Command>,
+    last_emit: Option<Instant>,
+    flush_interval: Duration,
+    max_batch_size: usize,
+}
+
+static COMMAND_BATCHER: OnceLock<Mutex<CommandBatcher>> = OnceLock::new();
+
+fn get_command_batcher() -> &'static Mutex<CommandBatcher> {
+    COMMAND_BATCHER.get_or_init(|| {
+        Mutex::new(CommandBatcher {
+            pending: Vec::new(),
+            last_emit: None,
+            flush_interval: Duration::from_millis(16),
+            max_batch_size: 200,
+        })
+    })
+}
+
+// Emits are done while holding the lock so commands reach the webview in order
+fn emit_batch(app_handle: &AppHandle, batcher: &mut CommandBatcher) {
+    if batcher.pending.is_empty() {
+        return;
+    }
+    let batch = std::mem::take(&mut batcher.pending);
+    batcher.last_emit = Some(Instant::now());
+    let _ = app_handle.emit("commands", &batch);
+}
+
+pub fn push(app_handle: &AppHandle, cmd: Command) {
+    let mut batcher = get_command_batcher().lock().unwrap();
+
+    let quiet = batcher
+        .last_emit
+        .is_none_or(|last| last.elapsed() >= batcher.flush_interval);
+    if batcher.pending.is_empty() && quiet {
+        batcher.last_emit = Some(Instant::now());
+        let _ = app_handle.emit("command", &cmd);
+        return;
+    }
+
+    batcher.pending.push(cmd);
+    if batcher.pending.len() >= batcher.max_batch_size {
+        emit_batch(app_handle, &mut batcher);
+    }
+}
+
+// Flush buffered commands every `flush_interval`
+pub fn start_flusher(
+    app_handle: AppHandle,
+    flush_interval: Duration,
+    max_batch_size: usize,
+) -> async_runtime::JoinHandle<()> {
+    {
+        let mut batcher = get_command_batcher().lock().unwrap();
+        batcher.flush_interval = flush_interval;
+        batcher.max_batch_size = max_batch_size.max(1);
+    }
+
+    async_runtime::spawn(async move {
+        let mut interval = interval(flush_interval);
+
+        loop {
+            interval.tick().await;
+            let mut batcher = get_command_batcher().lock().unwrap();
+            emit_batch(&app_handle, &mut batcher);
+        }
+    })
+}
+
+// Emit whatever is still buffered, e.g. before the server stops
+pub fn flush(app_handle: &AppHandle) {
+    let mut batcher = get_command_batcher().lock().unwrap();
+    emit_batch(app_handle, &mut batcher);
+}
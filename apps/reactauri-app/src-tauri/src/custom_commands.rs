@@ -0,0 +1,226 @@
+use crate::reactauri_core_server::{self, Command, CommandWithClientId};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use tauri::AppHandle;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomCommandArg {
+    pub name: String,
+    // Only "string" is defined by the client so far
+    #[serde(default = "default_arg_type")]
+    pub r#type: String,
+    // Arguments are required unless the client marks them optional or gives
+    // them a default, which is sent when the argument is left out
+    #[serde(default)]
+    pub optional: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<Value>,
+}
+
+fn default_arg_type() -> String {
+    "string".to_string()
+}
+
+// A `customCommand.register` payload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CustomCommand {
+    pub id: Value,
+    pub command: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub args: Vec<CustomCommandArg>,
+}
+
+impl CustomCommand {
+    // Check `args` against the declared arguments, returning them with the
+    // defaults of the missing ones filled in
+    fn validate_args(&self, args: Option<&Value>) -> Result<Option<Value>, String> {
+        let mut args = match args {
+            None | Some(Value::Null) => None,
+            Some(Value::Object(args)) => Some(args.clone()),
+            Some(_) => return Err(format!("Arguments of {} must be an object", self.command)),
+        };
+
+        let mut given = args.iter().flat_map(|args| args.keys());
+        if let Some(unknown) = given.find(|name| !self.args.iter().any(|arg| arg.name == **name)) {
+            return Err(format!("{} has no argument named {}", self.command, unknown));
+        }
+        for arg in &self.args {
+            let value = match args.as_ref().and_then(|args| args.get(&arg.name)) {
+                Some(value) if !value.is_null() => value,
+                _ => {
+                    if let Some(default) = &arg.default {
+                        args.get_or_insert_with(Map::new).insert(arg.name.clone(), default.clone());
+                    } else if !arg.optional {
+                        return Err(format!("Missing argument {} of {}", arg.name, self.command));
+                    }
+                    continue;
+                }
+            };
+            let valid = match arg.r#type.as_str() {
+                "string" => value.is_string(),
+                "number" => value.is_number(),
+                "boolean" => value.is_boolean(),
+                _ => true,
+            };
+            if !valid {
+                return Err(format!(
+                    "Argument {} of {} must be a {}",
+                    arg.name, self.command, arg.r#type
+                ));
+            }
+        }
+        Ok(args.map(Value::Object))
+    }
+}
+
+// Commands registered by each client, by client id
+static CUSTOM_COMMANDS: OnceLock<Mutex<HashMap<String, Vec<CustomCommand>>>> = OnceLock::new();
+
+fn get_custom_commands() -> &'static Mutex<HashMap<String, Vec<CustomCommand>>> {
+    CUSTOM_COMMANDS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// Keep the registry in sync with the `customCommand.*` commands of a client
+pub fn observe(cmd: &Command) {
+    let Some(client_id) = &cmd.client_id else {
+        return;
+    };
+    match cmd.r#type.as_str() {
+        // A reconnecting client registers its commands again
+        "client.intro" => {
+            get_custom_commands().lock().unwrap().remove(client_id);
+        }
+        "customCommand.register" => {
+            let Ok(command) = serde_json::from_value::<CustomCommand>(cmd.payload.clone()) else {
+                return;
+            };
+            let mut registry = get_custom_commands().lock().unwrap();
+            let commands = registry.entry(client_id.clone()).or_default();
+            commands.retain(|c| c.id != command.id && c.command != command.command);
+            commands.push(command);
+        }
+        "customCommand.unregister" => {
+            let mut registry = get_custom_commands().lock().unwrap();
+            if let Some(commands) = registry.get_mut(client_id) {
+                let id = cmd.payload.get("id").unwrap_or(&Value::Null);
+                commands.retain(|c| c.id != *id);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub fn remove_client(client_id: &str) {
+    get_custom_commands().lock().unwrap().remove(client_id);
+}
+
+pub fn clear() {
+    get_custom_commands().lock().unwrap().clear();
+}
+
+// Commands registered by one client, or by every client by client id
+#[tauri::command]
+pub fn list_custom_commands(client_id: Option<String>) -> HashMap<String, Vec<CustomCommand>> {
+    let registry = get_custom_commands().lock().unwrap();
+    registry
+        .iter()
+        .filter(|(id, _)| client_id.as_ref().is_none_or(|client_id| client_id == *id))
+        .map(|(id, commands)| (id.clone(), commands.clone()))
+        .collect()
+}
+
+// Run a registered command on a client, after checking its arguments
+#[tauri::command]
+pub async fn send_custom_command(
+    app: AppHandle,
+    client_id: String,
+    command: String,
+    args: Option<Value>,
+) -> Result<(), String> {
+    let args = {
+        let registry = get_custom_commands().lock().unwrap();
+        let registered = registry
+            .get(&client_id)
+            .and_then(|commands| commands.iter().find(|c| c.command == command))
+            .ok_or_else(|| format!("Client {} has no custom command {}", client_id, command))?;
+        registered.validate_args(args.as_ref())?
+    };
+    if !reactauri_core_server::is_client_connected(&client_id).await {
+        return Err(format!("Client {} is not connected", client_id));
+    }
+
+    let command = CommandWithClientId {
+        r#type: "custom".to_string(),
+        payload: serde_json::json!({ "command": command, "args": args }),
+        client_id,
+        important: false,
+        date: Some(chrono::Utc::now().to_rfc3339()),
+        delta_time: Some(0),
+    };
+    reactauri_core_server::send_command(app, command).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn command(args: Value) -> CustomCommand {
+        serde_json::from_value(json!({ "id": 1, "command": "login", "args": args })).unwrap()
+    }
+
+    #[test]
+    fn requires_arguments_declared_without_a_default() {
+        let login = command(json!([{ "name": "user" }, { "name": "remember", "type": "boolean" }]));
+        assert_eq!(
+            login.validate_args(Some(&json!({ "user": "ana", "remember": true }))),
+            Ok(Some(json!({ "user": "ana", "remember": true })))
+        );
+        assert!(login.validate_args(Some(&json!({ "user": "ana" }))).is_err());
+        assert!(login.validate_args(Some(&json!({ "user": "ana", "remember": null }))).is_err());
+        assert!(login.validate_args(None).is_err());
+    }
+
+    #[test]
+    fn leaves_out_optional_arguments_and_fills_in_defaults() {
+        let login = command(json!([
+            { "name": "user" },
+            { "name": "note", "optional": true },
+            { "name": "attempts", "type": "number", "default": 3 },
+        ]));
+        assert_eq!(
+            login.validate_args(Some(&json!({ "user": "ana" }))),
+            Ok(Some(json!({ "user": "ana", "attempts": 3 })))
+        );
+        assert_eq!(
+            login.validate_args(Some(&json!({ "user": "ana", "note": null, "attempts": 5 }))),
+            Ok(Some(json!({ "user": "ana", "note": null, "attempts": 5 })))
+        );
+        assert!(login.validate_args(Some(&json!({ "user": "ana", "note": 1 }))).is_err());
+    }
+
+    #[test]
+    fn passes_no_arguments_through() {
+        let refresh = command(json!([]));
+        assert_eq!(refresh.validate_args(None), Ok(None));
+        assert_eq!(refresh.validate_args(Some(&json!({}))), Ok(Some(json!({}))));
+
+        let defaults = command(json!([{ "name": "page", "type": "number", "default": 1 }]));
+        assert_eq!(defaults.validate_args(None), Ok(Some(json!({ "page": 1 }))));
+    }
+
+    #[test]
+    fn rejects_unknown_and_mistyped_arguments() {
+        let login = command(json!([{ "name": "user" }]));
+        assert!(login.validate_args(Some(&json!({ "user": "ana", "admin": true }))).is_err());
+        assert!(login.validate_args(Some(&json!({ "user": 1 }))).is_err());
+        assert!(login.validate_args(Some(&json!(["ana"]))).is_err());
+    }
+}
@@ -3,7 +3,32 @@
     windows_subsystem = "windows"
 )]
 
+mod action_scripts;
+mod api_analytics;
+mod api_replay;
+mod benchmarks;
+mod chunked_messages;
+mod client_requests;
+mod command_batcher;
+mod custom_commands;
+mod logging;
+mod mirror_mode;
+mod parse_errors;
+mod payload_store;
+mod payload_validation;
+mod permessage_deflate;
+mod rate_limiter;
 mod reactauri_core_server;
+mod redaction;
+mod repl;
+mod request_snippets;
+mod server_metrics;
+mod snapshot_library;
+mod state_diff;
+mod stats;
+mod subscription_cache;
+mod time_travel;
+mod wire_encoding;
 use tauri::{Manager};
 use tauri::menu::{MenuBuilder, MenuItem, SubmenuBuilder};
 
@@ -20,6 +45,35 @@ fn stop_core_server(app: tauri::AppHandle) {
     reactauri_core_server::stop_server(app);
 }
 
+#[tauri::command]
+fn get_core_server_options() -> reactauri_core_server::ServerOptions {
+    reactauri_core_server::get_options()
+}
+
+#[tauri::command]
+fn set_core_server_options(
+    app: tauri::AppHandle,
+    options: reactauri_core_server::ServerOptions,
+) -> Result<reactauri_core_server::ServerOptions, String> {
+    reactauri_core_server::set_options(app, options)
+}
+
+#[tauri::command]
+fn set_metrics_port(
+    app: tauri::AppHandle,
+    metrics_port: Option<u16>,
+) -> Result<reactauri_core_server::ServerOptions, String> {
+    reactauri_core_server::set_metrics_port(app, metrics_port)
+}
+
+#[tauri::command]
+fn set_validate_payloads(
+    app: tauri::AppHandle,
+    validate_payloads: bool,
+) -> Result<reactauri_core_server::ServerOptions, String> {
+    reactauri_core_server::set_validate_payloads(app, validate_payloads)
+}
+
 #[tauri::command]
 async fn send_command(
     app: tauri::AppHandle, 
@@ -35,7 +89,8 @@ async fn send_command(
         date: Some(chrono::Utc::now().to_rfc3339()),
         delta_time: Some(0),
     };
-    println!("send_command: {:?}", command);
+    log::debug!("send_command: {:?}", command);
+    client_requests::track_sent(&command.client_id, &command.r#type, &command.payload);
     reactauri_core_server::send_command(app, command).await;
 }
 
@@ -51,13 +106,77 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             start_core_server,
             stop_core_server,
+            get_core_server_options,
+            set_core_server_options,
+            set_metrics_port,
+            set_validate_payloads,
             send_command,
             get_device_list,
             reverse_tunnel_device,
             reload_app,
             shake_device,
+            logging::get_log_filters,
+            logging::set_log_filters,
+            logging::get_log_file_path,
+            logging::open_log_file,
+            logging::export_log_file,
+            server_metrics::get_server_metrics,
+            payload_store::get_payload,
+            client_requests::request_state_values,
+            client_requests::request_state_keys,
+            snapshot_library::list_snapshots,
+            snapshot_library::get_snapshot_state,
+            snapshot_library::create_snapshot,
+            snapshot_library::rename_snapshot,
+            snapshot_library::delete_snapshot,
+            snapshot_library::export_snapshot,
+            snapshot_library::import_snapshot,
+            snapshot_library::restore_snapshot,
+            state_diff::diff_states,
+            state_diff::compare_clients,
+            subscription_cache::get_subscribed_values,
+            subscription_cache::get_path_history,
+            subscription_cache::clear_path_history,
+            action_scripts::start_action_recording,
+            action_scripts::stop_action_recording,
+            action_scripts::list_action_scripts,
+            action_scripts::rename_action_script,
+            action_scripts::delete_action_script,
+            action_scripts::move_script_action,
+            action_scripts::remove_script_action,
+            action_scripts::update_script_action,
+            action_scripts::replay_action_script,
+            action_scripts::stop_action_replay,
+            time_travel::get_time_travel_timeline,
+            time_travel::time_travel_to,
+            mirror_mode::start_mirror,
+            mirror_mode::stop_mirror,
+            mirror_mode::list_mirrors,
+            custom_commands::list_custom_commands,
+            custom_commands::send_custom_command,
+            repl::repl_ls,
+            repl::repl_execute,
+            repl::get_repl_history,
+            repl::clear_repl_history,
+            benchmarks::get_benchmark_stats,
+            benchmarks::get_benchmark_history,
+            benchmarks::compare_benchmark_versions,
+            benchmarks::clear_benchmarks,
+            api_analytics::get_api_analytics,
+            api_analytics::clear_api_analytics,
+            request_snippets::get_request_snippets,
+            request_snippets::copy_request_snippet,
+            api_replay::replay_api_request,
+            redaction::get_redaction_settings,
+            redaction::set_redaction_settings,
+            redaction::get_redaction_hits,
+            redaction::reset_redaction_hits,
         ])
         .setup(|app| {
+            logging::init(app.handle())?;
+            redaction::init(app.handle());
+            reactauri_core_server::init_options(app.handle());
+
             #[cfg(debug_assertions)]
             {
                 let window: tauri::WebviewWindow = app.get_webview_window("main").unwrap();
@@ -179,7 +298,7 @@ pub fn run() {
                             .blocking_show();
                     }
                     "documentation" => {
-                        println!("Documentation clicked");
+                        log::debug!("Documentation clicked");
                         // Open URL
                         #[cfg(target_os = "macos")]
                         {
@@ -193,7 +312,7 @@ pub fn run() {
                         }
                     }
                     "quit" => {
-                        println!("Quit pressed");
+                        log::info!("Quit pressed");
                         std::process::exit(0);
                     }
                     "reload" => {
@@ -227,7 +346,7 @@ pub fn run() {
                         }
                     }
                     _ => {
-                        println!("unexpected menu event: {}", event.id().0);
+                        log::warn!("unexpected menu event: {}", event.id().0);
                     }
                 }
             });
@@ -236,7 +355,7 @@ pub fn run() {
             let app_handle = app.handle().clone();
             std::thread::spawn(move || {
                 if let Err(e) = android_commands::start_device_tracking_internal(app_handle) {
-                    println!("Failed to start Android device tracking: {}", e);
+                    log::error!("Failed to start Android device tracking: {}", e);
                 }
             });
             
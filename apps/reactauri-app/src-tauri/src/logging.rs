@@ -0,0 +1,279 @@
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{OnceLock, RwLock};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_log::{RotationStrategy, Target, TargetKind};
+use tauri_plugin_opener::OpenerExt;
+use tauri_plugin_store::StoreExt;
+
+const LOG_FILE_NAME: &str = "reactauri";
+const LOG_DIR_NAME: &str = "logs";
+const MAX_LOG_FILE_SIZE: u128 = 10 * 1024 * 1024;
+const MAX_LOG_FILES: usize = 5;
+const SETTINGS_STORE: &str = "logging.json";
+const SETTINGS_KEY: &str = "filters";
+
+// Log filter configuration, editable from the UI at runtime
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogFilters {
+    // Level used when no module filter matches, e.g. "info"
+    pub default_level: String,
+    // Module path prefix -> level, e.g. "reactauri_lib::reactauri_core_server" -> "trace"
+    #[serde(default)]
+    pub modules: HashMap<String, String>,
+}
+
+impl Default for LogFilters {
+    fn default() -> Self {
+        Self {
+            default_level: "info".to_string(),
+            modules: HashMap::new(),
+        }
+    }
+}
+
+// Parsed form of LogFilters used on the logging hot path
+#[derive(Debug, Clone)]
+struct ActiveFilters {
+    default_level: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl ActiveFilters {
+    fn parse(filters: &LogFilters) -> Result<Self, String> {
+        let default_level = parse_level(&filters.default_level)?;
+        let mut modules = filters
+            .modules
+            .iter()
+            .map(|(module, level)| Ok((module.clone(), parse_level(level)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        // Longest prefix first so the most specific filter wins
+        modules.sort_by_key(|(module, _)| std::cmp::Reverse(module.len()));
+        Ok(Self { default_level, modules })
+    }
+
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .find(|(module, _)| {
+                target == module || (target.starts_with(module.as_str()) && target[module.len()..].starts_with("::"))
+            })
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default_level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default_level, std::cmp::max)
+    }
+}
+
+impl Default for ActiveFilters {
+    fn default() -> Self {
+        Self {
+            default_level: LevelFilter::Info,
+            modules: Vec::new(),
+        }
+    }
+}
+
+static LOG_FILTERS: OnceLock<RwLock<(LogFilters, ActiveFilters)>> = OnceLock::new();
+static LOG_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+fn log_filters() -> &'static RwLock<(LogFilters, ActiveFilters)> {
+    LOG_FILTERS.get_or_init(|| RwLock::new((LogFilters::default(), ActiveFilters::default())))
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    level
+        .parse::<LevelFilter>()
+        .map_err(|_| format!("Invalid log level: {}", level))
+}
+
+fn is_enabled(metadata: &log::Metadata) -> bool {
+    let filters = log_filters().read().unwrap();
+    metadata.level() <= filters.1.level_for(metadata.target())
+}
+
+// Filters saved in the settings store, the defaults when none were saved
+fn saved_filters(saved: Option<serde_json::Value>) -> Result<LogFilters, String> {
+    let Some(saved) = saved else {
+        return Ok(LogFilters::default());
+    };
+    let filters: LogFilters = serde_json::from_value(saved).map_err(|e| e.to_string())?;
+    ActiveFilters::parse(&filters)?;
+    Ok(filters)
+}
+
+fn apply_filters(filters: LogFilters) -> Result<LogFilters, String> {
+    let active = ActiveFilters::parse(&filters)?;
+    log::set_max_level(active.max_level());
+    *log_filters().write().unwrap() = (filters.clone(), active);
+    Ok(filters)
+}
+
+// Install the logger: stdout plus a rotating file in the app data dir.
+// Must be called from setup, since the log folder depends on the app paths.
+pub fn init(app: &AppHandle) -> Result<(), Box<dyn std::error::Error>> {
+    let log_dir = app.path().app_data_dir()?.join(LOG_DIR_NAME);
+    let _ = LOG_DIR.set(log_dir.clone());
+
+    app.plugin(
+        tauri_plugin_log::Builder::new()
+            .clear_targets()
+            .targets([
+                Target::new(TargetKind::Stdout),
+                Target::new(TargetKind::Folder {
+                    path: log_dir,
+                    file_name: Some(LOG_FILE_NAME.to_string()),
+                }),
+            ])
+            .rotation_strategy(RotationStrategy::KeepSome(MAX_LOG_FILES))
+            .max_file_size(MAX_LOG_FILE_SIZE)
+            .level(LevelFilter::Trace)
+            .filter(is_enabled)
+            .build(),
+    )?;
+
+    // Restore filters saved from a previous session
+    let saved = app.store(SETTINGS_STORE).ok().and_then(|store| store.get(SETTINGS_KEY));
+    match saved_filters(saved) {
+        Ok(filters) => apply_filters(filters)?,
+        Err(e) => {
+            log::warn!("Ignoring saved log filters: {}", e);
+            apply_filters(LogFilters::default())?
+        }
+    };
+
+    log::info!("Logging to {}", current_log_file()?.display());
+    Ok(())
+}
+
+fn current_log_file() -> Result<PathBuf, String> {
+    LOG_DIR
+        .get()
+        .map(|dir| dir.join(format!("{}.log", LOG_FILE_NAME)))
+        .ok_or_else(|| "Logger is not initialized".to_string())
+}
+
+#[tauri::command]
+pub fn get_log_filters() -> LogFilters {
+    log_filters().read().unwrap().0.clone()
+}
+
+#[tauri::command]
+pub fn set_log_filters(app: AppHandle, filters: LogFilters) -> Result<LogFilters, String> {
+    let filters = apply_filters(filters)?;
+
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open log settings: {}", e))?;
+    store.set(SETTINGS_KEY, serde_json::to_value(&filters).map_err(|e| e.to_string())?);
+    store
+        .save()
+        .map_err(|e| format!("Failed to save log settings: {}", e))?;
+
+    log::info!("Log filters updated: {:?}", filters);
+    Ok(filters)
+}
+
+#[tauri::command]
+pub fn get_log_file_path() -> Result<String, String> {
+    Ok(current_log_file()?.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+pub fn open_log_file(app: AppHandle) -> Result<(), String> {
+    let path = current_log_file()?;
+    app.opener()
+        .open_path(path.to_string_lossy(), None::<&str>)
+        .map_err(|e| format!("Failed to open log file: {}", e))
+}
+
+#[tauri::command]
+pub fn export_log_file(destination: String) -> Result<(), String> {
+    log::logger().flush();
+    std::fs::copy(current_log_file()?, &destination)
+        .map(|_| ())
+        .map_err(|e| format!("Failed to export log file to {}: {}", destination, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn active(default_level: &str, modules: &[(&str, &str)]) -> ActiveFilters {
+        ActiveFilters::parse(&LogFilters {
+            default_level: default_level.to_string(),
+            modules: modules.iter().map(|(m, l)| (m.to_string(), l.to_string())).collect(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn uses_the_default_level_without_filters() {
+        let filters = active("warn", &[]);
+        assert_eq!(filters.level_for("reactauri_lib::redaction"), LevelFilter::Warn);
+        assert_eq!(filters.level_for(""), LevelFilter::Warn);
+        assert_eq!(filters.max_level(), LevelFilter::Warn);
+    }
+
+    #[test]
+    fn the_longest_matching_prefix_wins() {
+        let filters = active("info", &[("app", "warn"), ("app::server::ws", "trace"), ("app::server", "error")]);
+        assert_eq!(filters.level_for("app"), LevelFilter::Warn);
+        assert_eq!(filters.level_for("app::metrics"), LevelFilter::Warn);
+        assert_eq!(filters.level_for("app::server"), LevelFilter::Error);
+        assert_eq!(filters.level_for("app::server::http"), LevelFilter::Error);
+        assert_eq!(filters.level_for("app::server::ws::frames"), LevelFilter::Trace);
+        assert_eq!(filters.level_for("other"), LevelFilter::Info);
+        assert_eq!(filters.max_level(), LevelFilter::Trace);
+    }
+
+    #[test]
+    fn matches_whole_module_names_only() {
+        let filters = active("info", &[("foo", "debug")]);
+        assert_eq!(filters.level_for("foo"), LevelFilter::Debug);
+        assert_eq!(filters.level_for("foo::bar"), LevelFilter::Debug);
+        assert_eq!(filters.level_for("foobar"), LevelFilter::Info);
+        assert_eq!(filters.level_for("foobar::baz"), LevelFilter::Info);
+    }
+
+    #[test]
+    fn rejects_unknown_levels() {
+        assert!(ActiveFilters::parse(&LogFilters {
+            default_level: "loud".to_string(),
+            modules: HashMap::new(),
+        })
+        .is_err());
+        assert!(ActiveFilters::parse(&LogFilters {
+            default_level: "info".to_string(),
+            modules: HashMap::from([("foo".to_string(), "loud".to_string())]),
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn restores_saved_filters() {
+        let filters = LogFilters {
+            default_level: "debug".to_string(),
+            modules: HashMap::from([("foo".to_string(), "trace".to_string())]),
+        };
+        let saved = serde_json::to_value(&filters).unwrap();
+        assert_eq!(saved, json!({ "defaultLevel": "debug", "modules": { "foo": "trace" } }));
+        let restored = saved_filters(Some(saved)).unwrap();
+        assert_eq!(restored.default_level, "debug");
+        assert_eq!(restored.modules, filters.modules);
+
+        assert_eq!(saved_filters(None).unwrap().default_level, "info");
+        assert!(saved_filters(Some(json!({ "defaultLevel": "warn" }))).unwrap().modules.is_empty());
+        assert!(saved_filters(Some(json!({ "defaultLevel": "loud" }))).is_err());
+        assert!(saved_filters(Some(json!("info"))).is_err());
+    }
+}
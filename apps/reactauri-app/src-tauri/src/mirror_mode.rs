@@ -0,0 +1,283 @@
+use crate::reactauri_core_server::{self, CommandWithClientId};
+use log::{debug, info};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+use uuid::Uuid;
+
+// How long a target has to report a mirrored action before it is forgotten
+const ECHO_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Forwards the actions of a source client to its targets
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MirrorRule {
+    pub id: String,
+    pub source_client_id: String,
+    pub target_client_ids: Vec<String>,
+    // Action types to forward, every type when empty. A trailing `*` matches a prefix.
+    pub include: Vec<String>,
+    // Action types never forwarded, same syntax
+    pub exclude: Vec<String>,
+    pub forwarded: u64,
+}
+
+impl MirrorRule {
+    fn forwards(&self, action_type: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| type_matches(p, action_type)))
+            && !self.exclude.iter().any(|p| type_matches(p, action_type))
+    }
+}
+
+fn type_matches(pattern: &str, action_type: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => action_type.starts_with(prefix),
+        None => pattern == action_type,
+    }
+}
+
+#[derive(Default)]
+struct Mirrors {
+    rules: Vec<MirrorRule>,
+    // Actions dispatched to each target by a mirror. When the target reports
+    // one as completed it is not forwarded again, which would loop between
+    // clients mirroring each other.
+    echoes: HashMap<String, VecDeque<(String, Instant)>>,
+}
+
+impl Mirrors {
+    fn take_echo(&mut self, client_id: &str, action_type: &str) -> bool {
+        let Some(expected) = self.echoes.get_mut(client_id) else {
+            return false;
+        };
+        expected.retain(|(_, at)| at.elapsed() < ECHO_TIMEOUT);
+        let found = match expected.iter().position(|(name, _)| name == action_type) {
+            Some(index) => {
+                expected.remove(index);
+                true
+            }
+            None => false,
+        };
+        if expected.is_empty() {
+            self.echoes.remove(client_id);
+        }
+        found
+    }
+}
+
+static MIRRORS: OnceLock<Mutex<Mirrors>> = OnceLock::new();
+
+fn get_mirrors() -> &'static Mutex<Mirrors> {
+    MIRRORS.get_or_init(|| Mutex::new(Mirrors::default()))
+}
+
+impl Mirrors {
+    // Targets a `state.action.complete` payload of `client_id` goes to, with
+    // the action as the client sent it, noting the echoes to expect back
+    fn route(&mut self, client_id: &str, payload: &Value) -> Option<(String, Value, Vec<String>)> {
+        let action = payload.get("action")?;
+        let action_type = payload
+            .get("name")
+            .and_then(|n| n.as_str())
+            .or_else(|| action.get("type").and_then(|t| t.as_str()))
+            .unwrap_or("");
+        if self.rules.is_empty() || self.take_echo(client_id, action_type) {
+            return None;
+        }
+
+        let mut targets = Vec::new();
+        for rule in self
+            .rules
+            .iter_mut()
+            .filter(|rule| rule.source_client_id == client_id && rule.forwards(action_type))
+        {
+            rule.forwarded += 1;
+            for target in &rule.target_client_ids {
+                if !targets.contains(target) {
+                    targets.push(target.clone());
+                }
+            }
+        }
+        let now = Instant::now();
+        for target in &targets {
+            self.echoes
+                .entry(target.clone())
+                .or_default()
+                .push_back((action_type.to_string(), now));
+        }
+        Some((action_type.to_string(), action.clone(), targets))
+    }
+}
+
+// Forward a `state.action.complete` payload to the targets of the rules
+// whose source is `client_id`
+pub async fn forward(app_handle: &AppHandle, client_id: &str, payload: &Value) {
+    let Some((action_type, action, targets)) = get_mirrors().lock().unwrap().route(client_id, payload) else {
+        return;
+    };
+
+    for target in targets {
+        debug!("Mirroring {} from client {} to client {}", action_type, client_id, target);
+        let command = CommandWithClientId {
+            r#type: "state.action.dispatch".to_string(),
+            payload: serde_json::json!({ "action": action }),
+            client_id: target,
+            important: false,
+            date: Some(chrono::Utc::now().to_rfc3339()),
+            delta_time: Some(0),
+        };
+        reactauri_core_server::send_command(app_handle.clone(), command).await;
+    }
+}
+
+pub fn remove_client(client_id: &str) {
+    get_mirrors().lock().unwrap().echoes.remove(client_id);
+}
+
+#[tauri::command]
+pub fn start_mirror(
+    source_client_id: String,
+    target_client_ids: Vec<String>,
+    include: Option<Vec<String>>,
+    exclude: Option<Vec<String>>,
+) -> Result<MirrorRule, String> {
+    let target_client_ids: Vec<String> = target_client_ids
+        .into_iter()
+        .filter(|target| *target != source_client_id)
+        .collect();
+    if source_client_id.is_empty() || target_client_ids.is_empty() || target_client_ids.iter().any(|t| t.is_empty()) {
+        return Err("A mirror needs a source client and at least one other target client".to_string());
+    }
+
+    let rule = MirrorRule {
+        id: Uuid::new_v4().to_string(),
+        source_client_id,
+        target_client_ids,
+        include: include.unwrap_or_default(),
+        exclude: exclude.unwrap_or_default(),
+        forwarded: 0,
+    };
+    info!(
+        "Mirroring actions of client {} to {:?}",
+        rule.source_client_id, rule.target_client_ids
+    );
+    get_mirrors().lock().unwrap().rules.push(rule.clone());
+    Ok(rule)
+}
+
+#[tauri::command]
+pub fn stop_mirror(id: String) -> Result<(), String> {
+    let mut mirrors = get_mirrors().lock().unwrap();
+    let before = mirrors.rules.len();
+    mirrors.rules.retain(|rule| rule.id != id);
+    if mirrors.rules.len() == before {
+        return Err(format!("Mirror {} not found", id));
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn list_mirrors() -> Vec<MirrorRule> {
+    get_mirrors().lock().unwrap().rules.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redaction;
+    use serde_json::json;
+
+    fn mirror(source: &str, targets: &[&str]) -> MirrorRule {
+        MirrorRule {
+            id: format!("{}-mirror", source),
+            source_client_id: source.to_string(),
+            target_client_ids: targets.iter().map(|t| t.to_string()).collect(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            forwarded: 0,
+        }
+    }
+
+    #[test]
+    fn forwards_actions_as_sent() {
+        redaction::use_test_rules();
+        let mut mirrors = Mirrors {
+            rules: vec![mirror("a", &["b"])],
+            ..Default::default()
+        };
+        let action = json!({ "type": "LOGIN", "token": "secret-1" });
+        let (action_type, forwarded, targets) = mirrors.route("a", &json!({ "action": action })).unwrap();
+        assert_eq!(action_type, "LOGIN");
+        assert_eq!(forwarded, action);
+        assert_eq!(targets, ["b"]);
+    }
+
+    #[test]
+    fn include_and_exclude_patterns() {
+        let rule = MirrorRule {
+            include: vec!["todos/*".to_string(), "LOGOUT".to_string()],
+            exclude: vec!["todos/secret*".to_string()],
+            ..mirror("a", &["b"])
+        };
+        assert!(rule.forwards("todos/add"));
+        assert!(rule.forwards("todos/"));
+        assert!(rule.forwards("LOGOUT"));
+        assert!(!rule.forwards("LOGOUT_ALL"));
+        assert!(!rule.forwards("todo/add"));
+        assert!(!rule.forwards("todos/secretAdd"));
+
+        let everything = MirrorRule {
+            exclude: vec!["*".to_string()],
+            ..mirror("a", &["b"])
+        };
+        assert!(!everything.forwards("ANY"));
+        assert!(mirror("a", &["b"]).forwards("ANY"));
+    }
+
+    #[test]
+    fn skips_the_echo_of_a_mirrored_action() {
+        let mut mirrors = Mirrors {
+            rules: vec![mirror("a", &["b"]), mirror("b", &["a"])],
+            ..Default::default()
+        };
+        let payload = json!({ "name": "INCREMENT", "action": { "type": "INCREMENT" } });
+
+        let (_, _, targets) = mirrors.route("a", &payload).unwrap();
+        assert_eq!(targets, ["b"]);
+        // b dispatched the mirrored action and reports it: not sent back to a
+        assert!(mirrors.route("b", &payload).is_none());
+        // Its next own INCREMENT is forwarded again
+        let (_, _, targets) = mirrors.route("b", &payload).unwrap();
+        assert_eq!(targets, ["a"]);
+        assert_eq!(mirrors.rules[0].forwarded, 1);
+        assert_eq!(mirrors.rules[1].forwarded, 1);
+    }
+
+    #[test]
+    fn echoes_expire() {
+        let mut mirrors = Mirrors::default();
+        let long_ago = Instant::now() - ECHO_TIMEOUT - Duration::from_millis(1);
+        mirrors.echoes.insert("b".to_string(), VecDeque::from([("PING".to_string(), long_ago)]));
+        assert!(!mirrors.take_echo("b", "PING"));
+        assert!(mirrors.echoes.is_empty());
+    }
+
+    #[test]
+    fn actions_are_named_by_type_when_unnamed() {
+        let mut mirrors = Mirrors {
+            rules: vec![MirrorRule {
+                include: vec!["SAVE".to_string()],
+                ..mirror("a", &["b", "c"])
+            }],
+            ..Default::default()
+        };
+        let (action_type, _, targets) = mirrors.route("a", &json!({ "action": { "type": "SAVE" } })).unwrap();
+        assert_eq!(action_type, "SAVE");
+        assert_eq!(targets, ["b", "c"]);
+        assert!(mirrors.route("c", &json!({ "action": { "type": "SAVE" } })).is_none());
+        assert!(mirrors.route("a", &json!({ "name": "SAVE" })).is_none());
+    }
+}
@@ -0,0 +1,241 @@
+use crate::redaction;
+use crate::wire_encoding::WireEncoding;
+use serde::Serialize;
+use std::fmt::Write as _;
+use tauri::AppHandle;
+use tauri::Emitter;
+
+// Bytes of the raw message shown around the error location
+const EXCERPT_LENGTH: usize = 200;
+
+// Why a message could not be decoded into a `Command`. JSON errors carry a
+// line and column, CBOR errors a byte offset when the decoder knows it.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ParseError {
+    pub message: String,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+impl From<serde_json::Error> for ParseError {
+    fn from(e: serde_json::Error) -> Self {
+        Self {
+            message: e.to_string(),
+            line: Some(e.line()),
+            column: Some(e.column()),
+            offset: None,
+        }
+    }
+}
+
+impl From<rmp_serde::decode::Error> for ParseError {
+    fn from(e: rmp_serde::decode::Error) -> Self {
+        Self {
+            message: e.to_string(),
+            line: None,
+            column: None,
+            offset: None,
+        }
+    }
+}
+
+impl<T: std::fmt::Debug> From<ciborium::de::Error<T>> for ParseError {
+    fn from(e: ciborium::de::Error<T>) -> Self {
+        let offset = match &e {
+            ciborium::de::Error::Syntax(offset) => Some(*offset),
+            ciborium::de::Error::Semantic(offset, _) => *offset,
+            _ => None,
+        };
+        Self {
+            message: e.to_string(),
+            line: None,
+            column: None,
+            offset,
+        }
+    }
+}
+
+// Payload of the `commandParseError` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandParseError {
+    pub connection_id: u32,
+    pub client_id: Option<String>,
+    pub address: String,
+    pub encoding: WireEncoding,
+    pub date: String,
+    pub size: usize,
+    // Part of the message around the error, hex encoded for binary messages.
+    // Text excerpts go through the regex redaction rules, and there is no
+    // excerpt while rules that cannot be matched in raw bytes are on.
+    pub excerpt: Option<String>,
+    pub error: ParseError,
+}
+
+impl CommandParseError {
+    pub fn new(
+        connection_id: u32,
+        client_id: Option<String>,
+        address: String,
+        encoding: WireEncoding,
+        raw: &[u8],
+        error: ParseError,
+    ) -> Self {
+        let excerpt = match encoding {
+            WireEncoding::Json => redaction::redact_text(&text_excerpt(raw, &error)),
+            _ if redaction::is_active() => None,
+            _ => Some(binary_excerpt(raw, error.offset.unwrap_or(0))),
+        };
+        Self {
+            connection_id,
+            client_id,
+            address,
+            encoding,
+            date: chrono::Utc::now().to_rfc3339(),
+            size: raw.len(),
+            excerpt,
+            error,
+        }
+    }
+
+    // Message sent back to the client when `send_parse_errors_to_client` is on
+    pub fn to_client_message(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "commandParseError",
+            "payload": {
+                "error": self.error.message,
+                "line": self.error.line,
+                "column": self.error.column,
+                "offset": self.error.offset,
+                "excerpt": self.excerpt,
+            }
+        })
+    }
+}
+
+// Window of `EXCERPT_LENGTH` bytes starting a little before `position`
+fn window(len: usize, position: usize) -> (usize, usize) {
+    let start = position.min(len).saturating_sub(EXCERPT_LENGTH / 2);
+    (start, (start + EXCERPT_LENGTH).min(len))
+}
+
+fn is_continuation(byte: u8) -> bool {
+    byte & 0b1100_0000 == 0b1000_0000
+}
+
+fn text_excerpt(raw: &[u8], error: &ParseError) -> String {
+    // serde_json reports a one-based line and a column counted in bytes,
+    // column 0 meaning the error is at the start of the line
+    let position = match (error.line, error.column) {
+        (Some(line), Some(column)) if line > 1 => {
+            let line_start = raw
+                .iter()
+                .enumerate()
+                .filter(|(_, byte)| **byte == b'\n')
+                .nth(line - 2)
+                .map(|(index, _)| index + 1)
+                .unwrap_or(raw.len());
+            line_start + column.saturating_sub(1)
+        }
+        (Some(_), Some(column)) => column.saturating_sub(1),
+        _ => 0,
+    };
+
+    // Widen the window to whole characters
+    let (mut start, mut end) = window(raw.len(), position);
+    while start > 0 && is_continuation(raw[start]) {
+        start -= 1;
+    }
+    while end < raw.len() && is_continuation(raw[end]) {
+        end += 1;
+    }
+
+    let mut excerpt = String::new();
+    if start > 0 {
+        excerpt.push('…');
+    }
+    excerpt.push_str(&String::from_utf8_lossy(&raw[start..end]));
+    if end < raw.len() {
+        excerpt.push('…');
+    }
+    excerpt
+}
+
+fn binary_excerpt(raw: &[u8], offset: usize) -> String {
+    let (start, end) = window(raw.len(), offset);
+    let mut excerpt = format!("{:08x}:", start);
+    for byte in &raw[start..end] {
+        let _ = write!(excerpt, " {:02x}", byte);
+    }
+    excerpt
+}
+
+pub fn report(app_handle: &AppHandle, error: &CommandParseError) {
+    let _ = app_handle.emit("commandParseError", error);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json_error(raw: &[u8]) -> ParseError {
+        serde_json::from_slice::<serde_json::Value>(raw).unwrap_err().into()
+    }
+
+    #[test]
+    fn excerpts_the_start_of_a_message_failing_at_offset_zero() {
+        let raw = format!("x{}", "a".repeat(300));
+        let excerpt = text_excerpt(raw.as_bytes(), &json_error(raw.as_bytes()));
+        assert_eq!(excerpt, format!("{}…", &raw[..EXCERPT_LENGTH]));
+        assert_eq!(binary_excerpt(&[1, 2, 3], 0), "00000000: 01 02 03");
+    }
+
+    #[test]
+    fn excerpts_the_end_of_a_message_failing_at_eof() {
+        let raw = format!("{{\"a\": \"{}", "b".repeat(300));
+        let excerpt = text_excerpt(raw.as_bytes(), &json_error(raw.as_bytes()));
+        assert!(excerpt.starts_with('…'));
+        assert!(excerpt.ends_with("bbb"));
+        assert_eq!(binary_excerpt(&[1, 2, 3], 10), "00000000: 01 02 03");
+    }
+
+    #[test]
+    fn keeps_multi_byte_characters_whole() {
+        // The window starts in the middle of a two byte character
+        let raw = format!("{{\"a\": \"{}\"  x {}\"}}", "é".repeat(75), "é".repeat(149));
+        let error = json_error(raw.as_bytes());
+        assert_eq!((error.line, error.column), (Some(1), Some(161)));
+        let excerpt = text_excerpt(raw.as_bytes(), &error);
+        assert!(!excerpt.contains(char::REPLACEMENT_CHARACTER));
+        assert!(excerpt.contains("\"  x "));
+        assert_eq!(excerpt.trim_matches('…').len(), EXCERPT_LENGTH + 1);
+    }
+
+    #[test]
+    fn handles_errors_on_later_lines_and_past_the_end() {
+        let raw = "{\n\"ü\": x}".as_bytes();
+        let excerpt = text_excerpt(raw, &json_error(raw));
+        assert_eq!(excerpt, "{\n\"ü\": x}");
+        let error = ParseError {
+            message: String::new(),
+            line: Some(5),
+            column: Some(1),
+            offset: None,
+        };
+        assert_eq!(text_excerpt(raw, &error), "{\n\"ü\": x}");
+    }
+
+    #[test]
+    fn redacts_text_excerpts_and_withholds_binary_ones() {
+        redaction::use_test_rules();
+        let raw = b"{\"token\": \"secret-42\" oops}";
+        let error = CommandParseError::new(1, None, String::new(), WireEncoding::Json, raw, json_error(raw));
+        assert_eq!(error.excerpt.as_deref(), Some("{\"token\": \"[REDACTED]\" oops}"));
+        assert_eq!(error.to_client_message()["payload"]["excerpt"], "{\"token\": \"[REDACTED]\" oops}");
+
+        let binary = CommandParseError::new(1, None, String::new(), WireEncoding::Cbor, raw, json_error(raw));
+        assert_eq!(binary.excerpt, None);
+    }
+}
@@ -0,0 +1,293 @@
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+use uuid::Uuid;
+
+// Subtrees of an offloaded payload larger than this are replaced by a stub
+const MAX_INLINE_FIELD_BYTES: usize = 16 * 1024;
+// Number of bytes of an offloaded subtree kept as a preview
+const PREVIEW_LENGTH: usize = 200;
+// Oldest offloaded payloads are evicted once they take more than this
+const MAX_STORED_BYTES: usize = 256 * 1024 * 1024;
+// Same for the unredacted copies kept by `keep`, which have their own budget
+// so a flood of large commands does not evict them
+const MAX_KEPT_BYTES: usize = 64 * 1024 * 1024;
+
+struct StoredPayload {
+    value: Value,
+    size: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Pool {
+    Offloaded,
+    Kept,
+}
+
+// Payloads of a pool, oldest first
+#[derive(Default)]
+struct Queue {
+    order: VecDeque<String>,
+    total_size: usize,
+}
+
+#[derive(Default)]
+struct PayloadStore {
+    payloads: HashMap<String, StoredPayload>,
+    offloaded: Queue,
+    kept: Queue,
+}
+
+impl PayloadStore {
+    // Add a payload, evicting the oldest ones of its pool past `max_size` bytes
+    fn insert(&mut self, pool: Pool, handle: String, value: Value, size: usize, max_size: usize) {
+        let PayloadStore { payloads, offloaded, kept } = self;
+        let queue = match pool {
+            Pool::Offloaded => offloaded,
+            Pool::Kept => kept,
+        };
+        while queue.total_size + size > max_size {
+            let Some(oldest) = queue.order.pop_front() else {
+                break;
+            };
+            if let Some(evicted) = payloads.remove(&oldest) {
+                queue.total_size -= evicted.size;
+            }
+        }
+
+        queue.total_size += size;
+        queue.order.push_back(handle.clone());
+        payloads.insert(handle, StoredPayload { value, size });
+    }
+
+    fn get(&self, handle: &str, pointer: Option<&str>) -> Result<Value, String> {
+        let payload = self
+            .payloads
+            .get(handle)
+            .ok_or_else(|| format!("Payload {} not found, it may have been evicted", handle))?;
+
+        match pointer {
+            None | Some("") => Ok(payload.value.clone()),
+            Some(pointer) => payload
+                .value
+                .pointer(pointer)
+                .cloned()
+                .ok_or_else(|| format!("Path {} not found in payload {}", pointer, handle)),
+        }
+    }
+}
+
+// Stub left in place of an offloaded subtree
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OffloadedValue {
+    payload_handle: String,
+    pointer: String,
+    size: usize,
+    preview: String,
+    truncated: bool,
+}
+
+static PAYLOAD_STORE: OnceLock<Mutex<PayloadStore>> = OnceLock::new();
+
+fn get_payload_store() -> &'static Mutex<PayloadStore> {
+    PAYLOAD_STORE.get_or_init(|| Mutex::new(PayloadStore::default()))
+}
+
+struct CountingWriter(usize);
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Collects the first bytes of the output, then aborts serialization
+struct PreviewWriter(Vec<u8>);
+
+impl Write for PreviewWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let remaining = PREVIEW_LENGTH - self.0.len();
+        if remaining == 0 {
+            return Err(std::io::Error::other("preview complete"));
+        }
+        let take = remaining.min(buf.len());
+        self.0.extend_from_slice(&buf[..take]);
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// Serialized JSON size of a value, without allocating the string
+pub fn json_size(value: &Value) -> usize {
+    let mut writer = CountingWriter(0);
+    let _ = serde_json::to_writer(&mut writer, value);
+    writer.0
+}
+
+fn preview(value: &Value) -> String {
+    let mut writer = PreviewWriter(Vec::with_capacity(PREVIEW_LENGTH));
+    let _ = serde_json::to_writer(&mut writer, value);
+    // JSON is valid UTF-8, only the last character can be cut in half
+    match String::from_utf8(writer.0) {
+        Ok(preview) => preview,
+        Err(e) => {
+            let valid = e.utf8_error().valid_up_to();
+            let mut bytes = e.into_bytes();
+            bytes.truncate(valid);
+            String::from_utf8(bytes).unwrap_or_default()
+        }
+    }
+}
+
+fn escape_pointer_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+fn stub(handle: &str, pointer: &str, value: &Value, size: usize) -> Value {
+    serde_json::to_value(OffloadedValue {
+        payload_handle: handle.to_string(),
+        pointer: pointer.to_string(),
+        size,
+        preview: preview(value),
+        truncated: size > PREVIEW_LENGTH,
+    })
+    .unwrap_or(Value::Null)
+}
+
+// Keep small fields as they are and replace large subtrees with stubs
+fn truncate(value: &Value, handle: &str, pointer: &str) -> Value {
+    let size = json_size(value);
+    if size <= MAX_INLINE_FIELD_BYTES {
+        return value.clone();
+    }
+
+    if let Value::Object(object) = value {
+        let truncated: Map<String, Value> = object
+            .iter()
+            .map(|(key, child)| {
+                let child_pointer = format!("{}/{}", pointer, escape_pointer_token(key));
+                (key.clone(), truncate(child, handle, &child_pointer))
+            })
+            .collect();
+        let truncated = Value::Object(truncated);
+        if json_size(&truncated) <= MAX_INLINE_FIELD_BYTES {
+            return truncated;
+        }
+    }
+
+    stub(handle, pointer, value, size)
+}
+
+fn store(pool: Pool, handle: String, value: Value, size: usize) {
+    let max_size = match pool {
+        Pool::Offloaded => MAX_STORED_BYTES,
+        Pool::Kept => MAX_KEPT_BYTES,
+    };
+    get_payload_store()
+        .lock()
+        .unwrap()
+        .insert(pool, handle, value, size, max_size);
+}
+
+// Move a payload larger than `threshold` bytes into the store, leaving a
+// preview with large subtrees stubbed out, and return the handle to fetch it.
+// A threshold of 0 disables offloading.
+pub fn offload(payload: &mut Value, threshold: usize) -> Option<String> {
+    if threshold == 0 {
+        return None;
+    }
+    let size = json_size(payload);
+    if size <= threshold {
+        return None;
+    }
+
+    let handle = Uuid::new_v4().to_string();
+    let preview = truncate(payload, &handle, "");
+    let full = std::mem::replace(payload, preview);
+    store(Pool::Offloaded, handle.clone(), full, size);
+    Some(handle)
+}
+
+// Keep a payload in the store as is, returning the handle to fetch it
+pub fn keep(value: Value) -> String {
+    let handle = Uuid::new_v4().to_string();
+    let size = json_size(&value);
+    store(Pool::Kept, handle.clone(), value, size);
+    handle
+}
+
+// Fetch an offloaded payload, or the subtree at a JSON pointer (RFC 6901)
+#[tauri::command]
+pub fn get_payload(handle: String, pointer: Option<String>) -> Result<Value, String> {
+    get_payload_store().lock().unwrap().get(&handle, pointer.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn leaves_small_payloads_inline() {
+        let mut payload = json!({ "a": 1 });
+        assert_eq!(offload(&mut payload, 0), None);
+        assert_eq!(offload(&mut payload, 1024), None);
+        assert_eq!(payload, json!({ "a": 1 }));
+    }
+
+    #[test]
+    fn offloads_large_subtrees_and_fetches_them_back() {
+        let big = "x".repeat(2 * MAX_INLINE_FIELD_BYTES);
+        let full = json!({ "small": 1, "a/b": { "big": big } });
+        let mut payload = full.clone();
+        let handle = offload(&mut payload, 1024).unwrap();
+
+        assert_eq!(payload["small"], json!(1));
+        let stub = &payload["a/b"]["big"];
+        assert_eq!(stub["payloadHandle"], json!(handle));
+        assert_eq!(stub["pointer"], json!("/a~1b/big"));
+        assert_eq!(stub["truncated"], json!(true));
+        assert_eq!(stub["preview"].as_str().unwrap().len(), PREVIEW_LENGTH);
+
+        assert_eq!(get_payload(handle.clone(), None).unwrap(), full);
+        let pointer = stub["pointer"].as_str().unwrap().to_string();
+        assert_eq!(get_payload(handle.clone(), Some(pointer)).unwrap(), json!(big));
+        assert!(get_payload(handle, Some("/missing".to_string())).is_err());
+        assert!(get_payload("unknown".to_string(), None).is_err());
+    }
+
+    #[test]
+    fn cuts_previews_between_characters() {
+        // The quote puts the preview limit in the middle of a two byte character
+        let text = preview(&json!("é".repeat(PREVIEW_LENGTH)));
+        assert_eq!(text.len(), PREVIEW_LENGTH - 1);
+        assert!(!text.contains(char::REPLACEMENT_CHARACTER));
+    }
+
+    #[test]
+    fn evicts_the_oldest_payloads_of_a_pool_only() {
+        let mut store = PayloadStore::default();
+        store.insert(Pool::Kept, "raw".to_string(), json!(0), 40, 100);
+        for index in 0..3 {
+            store.insert(Pool::Offloaded, index.to_string(), json!(index), 40, 100);
+        }
+
+        assert!(store.get("0", None).is_err());
+        assert_eq!(store.get("1", None).unwrap(), json!(1));
+        assert_eq!(store.get("2", None).unwrap(), json!(2));
+        assert_eq!(store.get("raw", None).unwrap(), json!(0));
+        assert_eq!(store.offloaded.total_size, 80);
+        assert_eq!(store.kept.total_size, 40);
+    }
+}
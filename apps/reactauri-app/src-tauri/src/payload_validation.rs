@@ -0,0 +1,182 @@
+use jsonschema::Validator;
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+// JSON Schemas for command payloads, written from the payload types in
+// lib/reactotron-core-contract. Commands of other types are not validated.
+const SCHEMAS: [(&str, &str); 7] = [
+    ("log", include_str!("payload_schemas/log.json")),
+    ("api.response", include_str!("payload_schemas/api.response.json")),
+    ("state.action.complete", include_str!("payload_schemas/state.action.complete.json")),
+    ("state.backup.response", include_str!("payload_schemas/state.backup.response.json")),
+    ("state.keys.response", include_str!("payload_schemas/state.keys.response.json")),
+    ("state.values.change", include_str!("payload_schemas/state.values.change.json")),
+    ("state.values.response", include_str!("payload_schemas/state.values.response.json")),
+];
+
+// Warnings reported for a single command
+const MAX_WARNINGS: usize = 10;
+
+// Attached to a command whose payload does not match the contract
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationWarning {
+    // JSON pointer to the offending value in the payload
+    pub path: String,
+    pub message: String,
+}
+
+// Mirrors `ServerOptions::validate_payloads`, read for every command so it
+// can be switched without reconnecting clients
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+static VALIDATORS: OnceLock<HashMap<&'static str, Validator>> = OnceLock::new();
+
+fn get_validators() -> &'static HashMap<&'static str, Validator> {
+    VALIDATORS.get_or_init(|| {
+        SCHEMAS
+            .iter()
+            .filter_map(|(command_type, schema)| {
+                let validator = serde_json::from_str::<Value>(schema)
+                    .map_err(|e| e.to_string())
+                    .and_then(|schema| jsonschema::validator_for(&schema).map_err(|e| e.to_string()));
+                match validator {
+                    Ok(validator) => Some((*command_type, validator)),
+                    Err(e) => {
+                        error!("Invalid payload schema for {}: {}", command_type, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    })
+}
+
+// Check a payload against the schema for its command type, None when it matches
+pub fn validate(command_type: &str, payload: &Value) -> Option<Vec<ValidationWarning>> {
+    let validator = get_validators().get(command_type)?;
+    let warnings: Vec<ValidationWarning> = validator
+        .iter_errors(payload)
+        .take(MAX_WARNINGS)
+        .map(|e| ValidationWarning {
+            path: e.instance_path.as_str().to_string(),
+            message: e.to_string(),
+        })
+        .collect();
+
+    if warnings.is_empty() {
+        None
+    } else {
+        Some(warnings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    // Payloads shaped as reactotron-core-client sends them
+    fn samples() -> Vec<(&'static str, Value)> {
+        vec![
+            ("log", json!({ "level": "debug", "message": { "any": "thing" } })),
+            ("log", json!({ "level": "warn", "message": "careful" })),
+            (
+                "log",
+                json!({
+                    "level": "error",
+                    "message": "boom",
+                    "stack": [{ "fileName": "App.tsx", "functionName": "render", "lineNumber": 12, "columnNumber": 4 }],
+                }),
+            ),
+            ("log", json!({ "level": "error", "message": "boom", "stack": "Error: boom\n    at render" })),
+            (
+                "api.response",
+                json!({
+                    "request": { "url": "https://example.com/users", "method": "GET", "headers": { "Accept": "application/json" }, "data": null, "params": { "page": 1 } },
+                    "response": { "status": 200, "headers": { "content-length": 12 }, "body": { "users": [] } },
+                    "duration": 42.5,
+                }),
+            ),
+            ("state.action.complete", json!({ "name": "INCREMENT", "action": { "type": "INCREMENT" }, "ms": 3 })),
+            ("state.backup.response", json!({ "state": { "counter": { "count": 1 } } })),
+            ("state.keys.response", json!({ "path": "counter", "keys": ["count"], "valid": true })),
+            ("state.keys.response", json!({ "path": null, "keys": null, "valid": false })),
+            ("state.values.change", json!({ "changes": [{ "path": "counter.count", "value": 2 }] })),
+            ("state.values.response", json!({ "path": "counter", "value": { "count": 1 }, "valid": true })),
+        ]
+    }
+
+    fn warning_paths(command_type: &str, payload: Value) -> Vec<String> {
+        validate(command_type, &payload)
+            .expect("payload should not match")
+            .into_iter()
+            .map(|w| w.path)
+            .collect()
+    }
+
+    #[test]
+    fn every_schema_compiles() {
+        assert_eq!(get_validators().len(), SCHEMAS.len());
+    }
+
+    #[test]
+    fn accepts_contract_payloads() {
+        for (command_type, payload) in samples() {
+            let warnings = validate(command_type, &payload);
+            assert!(warnings.is_none(), "{} {}: {:?}", command_type, payload, warnings);
+        }
+    }
+
+    #[test]
+    fn flags_payloads_that_break_the_contract() {
+        assert_eq!(warning_paths("log", json!({ "level": "info", "message": "hi" })), ["/level"]);
+        assert_eq!(
+            warning_paths("log", json!({ "level": "error", "message": "boom", "stack": [{ "fileName": "App.tsx" }] })),
+            ["/stack"]
+        );
+        assert_eq!(
+            warning_paths("api.response", json!({ "request": { "url": "/" }, "response": { "status": "200" }, "duration": 1 })),
+            ["/response/status"]
+        );
+        assert_eq!(
+            warning_paths("api.response", json!({ "request": {}, "response": { "status": 200 }, "duration": 1 })),
+            ["/request"]
+        );
+        assert_eq!(warning_paths("state.action.complete", json!({ "name": 1, "action": {} })), ["/name"]);
+        assert_eq!(warning_paths("state.backup.response", json!({ "state": "not an object" })), ["/state"]);
+        assert_eq!(
+            warning_paths("state.keys.response", json!({ "path": "a", "keys": ["b", 2], "valid": true })),
+            ["/keys/1"]
+        );
+        assert_eq!(
+            warning_paths("state.values.change", json!({ "changes": [{ "value": 1 }] })),
+            ["/changes/0"]
+        );
+        assert_eq!(warning_paths("state.values.response", json!({ "path": "a", "value": 1 })), [""]);
+    }
+
+    #[test]
+    fn skips_types_without_a_schema() {
+        assert!(validate("display", &json!("anything")).is_none());
+    }
+
+    #[test]
+    fn caps_the_warnings() {
+        let keys: Vec<Value> = (0..20).map(Value::from).collect();
+        let warnings = validate("state.keys.response", &json!({ "path": "a", "keys": keys, "valid": true })).unwrap();
+        assert_eq!(warnings.len(), MAX_WARNINGS);
+    }
+}
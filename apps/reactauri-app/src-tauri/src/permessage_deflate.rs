@@ -0,0 +1,843 @@
+use crate::server_metrics;
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+
+// permessage-deflate (RFC 7692) for client connections.
+//
+// tungstenite has no support for websocket extensions (none up to 0.30), and
+// no maintained crate adds permessage-deflate to it, so `DeflateStream` sits
+// between the TCP stream and tungstenite: it passes the HTTP handshake through,
+// then inflates compressed frames from the client and compresses large frames
+// to the client, so tungstenite only ever sees plain frames.
+
+const EXTENSIONS_HEADER: &str = "Sec-WebSocket-Extensions";
+// Appended to each compressed message by the sender, stripped on the wire
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+// Smaller outgoing messages are not worth compressing
+const MIN_COMPRESS_SIZE: usize = 256;
+const HEADER_END: &[u8] = b"\r\n\r\n";
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+const MAX_BUFFERED_WRITE: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompressionOptions {
+    pub enabled: bool,
+    // zlib compression level for messages sent to clients, 0-9
+    pub level: u32,
+}
+
+impl Default for CompressionOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            level: 6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeflateParams {
+    server_no_context_takeover: bool,
+}
+
+// Filled in by the handshake callback once the extension is agreed on
+pub type Negotiation = Arc<Mutex<Option<DeflateParams>>>;
+
+// Pick the first permessage-deflate offer we can honour, returning the agreed
+// parameters and the response header value
+fn negotiate(offers: &str) -> Option<(DeflateParams, String)> {
+    'offers: for offer in offers.split(',') {
+        let mut parts = offer.split(';').map(str::trim);
+        if parts.next() != Some("permessage-deflate") {
+            continue;
+        }
+
+        let mut params = DeflateParams::default();
+        let mut response = vec!["permessage-deflate".to_string()];
+        for param in parts {
+            let (name, value) = match param.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (param, None),
+            };
+            match (name, value) {
+                ("server_no_context_takeover", None) => {
+                    params.server_no_context_takeover = true;
+                    response.push(name.to_string());
+                }
+                // Our inflater copes with or without client context takeover,
+                // and with any client window size
+                ("client_no_context_takeover", None) | ("client_max_window_bits", _) => {}
+                ("server_max_window_bits", Some("15")) => response.push("server_max_window_bits=15".to_string()),
+                _ => continue 'offers,
+            }
+        }
+        return Some((params, response.join("; ")));
+    }
+    None
+}
+
+// Accept a permessage-deflate offer from the client handshake, if there is one
+pub fn negotiate_handshake(request: &Request, mut response: Response, negotiation: &Negotiation) -> Response {
+    let offers = request
+        .headers()
+        .get_all(EXTENSIONS_HEADER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    if let Some((params, header)) = negotiate(&offers) {
+        if let Ok(value) = HeaderValue::from_str(&header) {
+            response.headers_mut().insert(EXTENSIONS_HEADER, value);
+            *negotiation.lock().unwrap() = Some(params);
+        }
+    }
+    response
+}
+
+struct FrameHeader {
+    fin: bool,
+    rsv1: bool,
+    opcode: u8,
+    mask: Option<[u8; 4]>,
+    header_len: usize,
+    payload_len: usize,
+}
+
+impl FrameHeader {
+    fn is_data(&self) -> bool {
+        self.opcode == 0x1 || self.opcode == 0x2
+    }
+
+    fn is_continuation(&self) -> bool {
+        self.opcode == 0x0
+    }
+}
+
+// Parse a frame header, or None if `buf` does not hold all of it yet
+fn parse_header(buf: &[u8]) -> Option<FrameHeader> {
+    if buf.len() < 2 {
+        return None;
+    }
+    let masked = buf[1] & 0x80 != 0;
+    let (payload_len, mut header_len) = match buf[1] & 0x7f {
+        126 => (u16::from_be_bytes(buf.get(2..4)?.try_into().ok()?) as u64, 4),
+        127 => (u64::from_be_bytes(buf.get(2..10)?.try_into().ok()?), 10),
+        len => (len as u64, 2),
+    };
+    let mask = if masked {
+        let key: [u8; 4] = buf.get(header_len..header_len + 4)?.try_into().ok()?;
+        header_len += 4;
+        Some(key)
+    } else {
+        None
+    };
+
+    Some(FrameHeader {
+        fin: buf[0] & 0x80 != 0,
+        rsv1: buf[0] & 0x40 != 0,
+        opcode: buf[0] & 0x0f,
+        mask,
+        header_len,
+        payload_len: usize::try_from(payload_len).unwrap_or(usize::MAX),
+    })
+}
+
+fn write_header(out: &mut Vec<u8>, fin: bool, rsv1: bool, opcode: u8, mask: Option<[u8; 4]>, len: usize) {
+    out.push(((fin as u8) << 7) | ((rsv1 as u8) << 6) | opcode);
+    let mask_bit = if mask.is_some() { 0x80 } else { 0 };
+    if len < 126 {
+        out.push(mask_bit | len as u8);
+    } else if len <= u16::MAX as usize {
+        out.push(mask_bit | 126);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(mask_bit | 127);
+        out.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+    if let Some(key) = mask {
+        out.extend_from_slice(&key);
+    }
+}
+
+fn apply_mask(data: &mut [u8], key: [u8; 4]) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= key[i % 4];
+    }
+}
+
+// Feed bytes to a header matcher, returning the offset just past "\r\n\r\n"
+fn find_header_end(matched: &mut usize, data: &[u8]) -> Option<usize> {
+    for (i, &byte) in data.iter().enumerate() {
+        *matched = if byte == HEADER_END[*matched] {
+            *matched + 1
+        } else if byte == b'\r' {
+            1
+        } else {
+            0
+        };
+        if *matched == HEADER_END.len() {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+struct ReadHalf {
+    // Reused for every read from the inner stream
+    chunk: Box<[u8]>,
+    handshake_matched: usize,
+    handshake_done: bool,
+    eof: bool,
+    raw: Vec<u8>,
+    out: Vec<u8>,
+    out_pos: usize,
+    inflater: Decompress,
+    in_compressed_message: bool,
+    message_size: usize,
+}
+
+struct WriteHalf {
+    handshake_matched: usize,
+    handshake_done: bool,
+    raw: Vec<u8>,
+    out: Vec<u8>,
+    out_pos: usize,
+    deflater: Compress,
+}
+
+pub struct DeflateStream<S> {
+    inner: S,
+    connection_id: u32,
+    negotiation: Negotiation,
+    max_frame_size: usize,
+    max_message_size: usize,
+    read: ReadHalf,
+    write: WriteHalf,
+}
+
+impl<S> DeflateStream<S> {
+    pub fn new(
+        inner: S,
+        connection_id: u32,
+        negotiation: Negotiation,
+        options: &CompressionOptions,
+        max_frame_size: usize,
+        max_message_size: usize,
+    ) -> Self {
+        Self {
+            inner,
+            connection_id,
+            negotiation,
+            max_frame_size,
+            max_message_size,
+            read: ReadHalf {
+                chunk: vec![0u8; READ_CHUNK_SIZE].into_boxed_slice(),
+                handshake_matched: 0,
+                handshake_done: false,
+                eof: false,
+                raw: Vec::new(),
+                out: Vec::new(),
+                out_pos: 0,
+                inflater: Decompress::new(false),
+                in_compressed_message: false,
+                message_size: 0,
+            },
+            write: WriteHalf {
+                handshake_matched: 0,
+                handshake_done: false,
+                raw: Vec::new(),
+                out: Vec::new(),
+                out_pos: 0,
+                deflater: Compress::new(Compression::new(options.level.min(9)), false),
+            },
+        }
+    }
+
+    fn params(&self) -> Option<DeflateParams> {
+        *self.negotiation.lock().unwrap()
+    }
+
+    // Inflate `input` into `out`, stopping early once `out` exceeds `limit`
+    fn inflate(&mut self, input: &[u8], out: &mut Vec<u8>, limit: usize) -> io::Result<()> {
+        let mut consumed = 0;
+        loop {
+            out.reserve((input.len() - consumed).max(4096) * 4);
+            let before = self.read.inflater.total_in();
+            let status = self.read.inflater
+                .decompress_vec(&input[consumed..], out, FlushDecompress::Sync)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            consumed += (self.read.inflater.total_in() - before) as usize;
+
+            if status == Status::StreamEnd {
+                self.read.inflater.reset(false);
+            }
+            if out.len() > limit || (consumed == input.len() && out.len() < out.capacity()) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn deflate(&mut self, input: &[u8], no_context_takeover: bool) -> io::Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(input.len() / 2 + 64);
+        let mut consumed = 0;
+        loop {
+            let before = self.write.deflater.total_in();
+            self.write.deflater
+                .compress_vec(&input[consumed..], &mut out, FlushCompress::Sync)
+                .map_err(io::Error::other)?;
+            consumed += (self.write.deflater.total_in() - before) as usize;
+
+            if consumed == input.len() && out.len() < out.capacity() {
+                break;
+            }
+            out.reserve(out.capacity().max(4096));
+        }
+        if out.ends_with(&DEFLATE_TAIL) {
+            out.truncate(out.len() - DEFLATE_TAIL.len());
+        }
+        if no_context_takeover {
+            self.write.deflater.reset();
+        }
+        Ok(out)
+    }
+
+    // Move complete client frames from `read.raw` to `read.out`, inflating compressed ones
+    fn process_read(&mut self) -> io::Result<()> {
+        if !self.read.handshake_done {
+            let Some(end) = find_header_end(&mut self.read.handshake_matched, &self.read.raw) else {
+                self.read.out.append(&mut self.read.raw);
+                return Ok(());
+            };
+            self.read.out.extend(self.read.raw.drain(..end));
+            self.read.handshake_done = true;
+        }
+
+        loop {
+            if self.read.eof && !self.read.raw.is_empty() {
+                self.read.out.append(&mut self.read.raw);
+                return Ok(());
+            }
+            let Some(header) = parse_header(&self.read.raw) else {
+                return Ok(());
+            };
+            // Rejected from the header, before the frame is buffered
+            if header.payload_len > self.max_frame_size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Frame of {} bytes exceeds the limit of {} bytes", header.payload_len, self.max_frame_size),
+                ));
+            }
+            let frame_len = header.header_len + header.payload_len;
+            if self.read.raw.len() < frame_len {
+                return Ok(());
+            }
+            let mut frame: Vec<u8> = self.read.raw.drain(..frame_len).collect();
+
+            let compressed = (header.is_data() && header.rsv1)
+                || (header.is_continuation() && self.read.in_compressed_message);
+            if !compressed || self.params().is_none() {
+                if header.is_data() {
+                    self.read.in_compressed_message = false;
+                }
+                self.read.out.append(&mut frame);
+                continue;
+            }
+
+            if header.is_data() {
+                self.read.message_size = 0;
+            }
+            self.read.in_compressed_message = !header.fin;
+
+            let payload = &mut frame[header.header_len..];
+            if let Some(key) = header.mask {
+                apply_mask(payload, key);
+            }
+            // Inflated frames are held to the same limits tungstenite applies
+            let limit = self.max_frame_size.min(self.max_message_size.saturating_sub(self.read.message_size));
+            let mut inflated = Vec::new();
+            self.inflate(&frame[header.header_len..], &mut inflated, limit)?;
+            if header.fin && inflated.len() <= limit {
+                self.inflate(&DEFLATE_TAIL, &mut inflated, limit)?;
+            }
+            // Checked here rather than by tungstenite, which would only see the
+            // message once it is inflated in full
+            if inflated.len() > limit {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "Compressed message inflates past {} bytes per frame or {} bytes in total",
+                        self.max_frame_size, self.max_message_size
+                    ),
+                ));
+            }
+            self.read.message_size += inflated.len();
+            server_metrics::record_compressed_in(self.connection_id, header.payload_len, inflated.len());
+
+            if let Some(key) = header.mask {
+                apply_mask(&mut inflated, key);
+            }
+            write_header(&mut self.read.out, header.fin, false, header.opcode, header.mask, inflated.len());
+            self.read.out.append(&mut inflated);
+        }
+    }
+
+    // Move complete server frames from `write.raw` to `write.out`, compressing large ones
+    fn process_write(&mut self) -> io::Result<()> {
+        if !self.write.handshake_done {
+            let Some(end) = find_header_end(&mut self.write.handshake_matched, &self.write.raw) else {
+                self.write.out.append(&mut self.write.raw);
+                return Ok(());
+            };
+            self.write.out.extend(self.write.raw.drain(..end));
+            self.write.handshake_done = true;
+        }
+
+        let Some(params) = self.params() else {
+            self.write.out.append(&mut self.write.raw);
+            return Ok(());
+        };
+
+        while let Some(header) = parse_header(&self.write.raw) {
+            let frame_len = header.header_len.saturating_add(header.payload_len);
+            if self.write.raw.len() < frame_len {
+                break;
+            }
+            let mut frame: Vec<u8> = self.write.raw.drain(..frame_len).collect();
+
+            // Only whole, unmasked data messages are compressed
+            if header.is_data() && header.fin && !header.rsv1 && header.mask.is_none() && header.payload_len >= MIN_COMPRESS_SIZE {
+                let compressed = self.deflate(&frame[header.header_len..], params.server_no_context_takeover)?;
+                if compressed.len() < header.payload_len {
+                    server_metrics::record_compressed_out(self.connection_id, header.payload_len, compressed.len());
+                    write_header(&mut self.write.out, true, true, header.opcode, None, compressed.len());
+                    self.write.out.extend_from_slice(&compressed);
+                    continue;
+                }
+            }
+            self.write.out.append(&mut frame);
+        }
+        Ok(())
+    }
+}
+
+impl<S: AsyncWrite + Unpin> DeflateStream<S> {
+    // Write as much of `write.out` to the inner stream as it accepts
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.write.out_pos < self.write.out.len() {
+            match Pin::new(&mut self.inner).poll_write(cx, &self.write.out[self.write.out_pos..]) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Poll::Ready(Ok(written)) => self.write.out_pos += written,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        self.write.out.clear();
+        self.write.out_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for DeflateStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.read.out_pos < this.read.out.len() {
+                let available = &this.read.out[this.read.out_pos..];
+                let len = available.len().min(buf.remaining());
+                buf.put_slice(&available[..len]);
+                this.read.out_pos += len;
+                if this.read.out_pos == this.read.out.len() {
+                    this.read.out.clear();
+                    this.read.out_pos = 0;
+                }
+                return Poll::Ready(Ok(()));
+            }
+            if this.read.eof {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut chunk_buf = ReadBuf::new(&mut this.read.chunk);
+            match Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf) {
+                Poll::Ready(Ok(())) => {
+                    let filled = chunk_buf.filled().len();
+                    if filled == 0 {
+                        this.read.eof = true;
+                    }
+                    this.read.raw.extend_from_slice(&this.read.chunk[..filled]);
+                    this.process_read()?;
+                }
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for DeflateStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if let Poll::Ready(Err(e)) = this.poll_drain(cx) {
+            return Poll::Ready(Err(e));
+        }
+        if this.write.out.len() - this.write.out_pos > MAX_BUFFERED_WRITE {
+            return Poll::Pending;
+        }
+
+        this.write.raw.extend_from_slice(buf);
+        this.process_write()?;
+        if let Poll::Ready(Err(e)) = this.poll_drain(cx) {
+            return Poll::Ready(Err(e));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match this.poll_drain(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.inner).poll_flush(cx),
+            other => other,
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match this.poll_drain(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.inner).poll_shutdown(cx),
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+    use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::WebSocketStream;
+
+    const MASK: [u8; 4] = [0x12, 0x34, 0x56, 0x78];
+
+    type Server = WebSocketStream<DeflateStream<DuplexStream>>;
+
+    // Open a connection offering `extensions`, returning the server side, the
+    // client side and whether permessage-deflate was agreed on
+    async fn connect(extensions: &str, max_message_size: usize) -> (Server, DuplexStream, Option<DeflateParams>) {
+        connect_with_limits(extensions, max_message_size, max_message_size).await
+    }
+
+    async fn connect_with_limits(
+        extensions: &str,
+        max_frame_size: usize,
+        max_message_size: usize,
+    ) -> (Server, DuplexStream, Option<DeflateParams>) {
+        let (mut client, server) = duplex(16 * 1024 * 1024);
+        let negotiation = Negotiation::default();
+        let stream = DeflateStream::new(
+            server,
+            0,
+            negotiation.clone(),
+            &CompressionOptions::default(),
+            max_frame_size,
+            max_message_size,
+        );
+        let config = WebSocketConfig::default()
+            .max_frame_size(Some(max_frame_size))
+            .max_message_size(Some(max_message_size));
+        let server_negotiation = negotiation.clone();
+        let accept = tokio::spawn(async move {
+            tokio_tungstenite::accept_hdr_async_with_config(
+                stream,
+                move |request: &Request, response: Response| {
+                    Ok(negotiate_handshake(request, response, &server_negotiation))
+                },
+                Some(config),
+            )
+            .await
+            .unwrap()
+        });
+
+        let request = format!(
+            "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+             Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\
+             Sec-WebSocket-Extensions: {}\r\n\r\n",
+            extensions
+        );
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        while !response.ends_with(HEADER_END) {
+            response.push(client.read_u8().await.unwrap());
+        }
+
+        let server = accept.await.unwrap();
+        let params = *negotiation.lock().unwrap();
+        (server, client, params)
+    }
+
+    fn client_frame(fin: bool, rsv1: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mut masked = payload.to_vec();
+        apply_mask(&mut masked, MASK);
+        let mut frame = Vec::new();
+        write_header(&mut frame, fin, rsv1, opcode, Some(MASK), masked.len());
+        frame.extend_from_slice(&masked);
+        frame
+    }
+
+    // Compress as a client would, without the trailing empty block
+    fn compress(deflater: &mut Compress, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len() + 1024);
+        let before = deflater.total_in();
+        deflater.compress_vec(data, &mut out, FlushCompress::Sync).unwrap();
+        assert_eq!((deflater.total_in() - before) as usize, data.len());
+        assert!(out.ends_with(&DEFLATE_TAIL));
+        out.truncate(out.len() - DEFLATE_TAIL.len());
+        out
+    }
+
+    fn inflate(inflater: &mut Decompress, payload: &[u8]) -> Vec<u8> {
+        let mut input = payload.to_vec();
+        input.extend_from_slice(&DEFLATE_TAIL);
+        let mut out = Vec::with_capacity(16 * 1024 * 1024);
+        inflater.decompress_vec(&input, &mut out, FlushDecompress::Sync).unwrap();
+        out
+    }
+
+    async fn read_frame(client: &mut DuplexStream) -> (FrameHeader, Vec<u8>) {
+        let mut buf = Vec::new();
+        loop {
+            if let Some(header) = parse_header(&buf) {
+                if buf.len() == header.header_len + header.payload_len {
+                    let payload = buf.split_off(header.header_len);
+                    return (header, payload);
+                }
+            }
+            buf.push(client.read_u8().await.unwrap());
+        }
+    }
+
+    async fn next_text(server: &mut Server) -> String {
+        match server.next().await {
+            Some(Ok(Message::Text(text))) => text.to_string(),
+            other => panic!("Expected a text message, got {:?}", other),
+        }
+    }
+
+    fn json_like(len: usize) -> String {
+        let mut text = String::from("[");
+        while text.len() < len {
+            text.push_str(&format!("{{\"id\":{},\"name\":\"item\"}},", text.len()));
+        }
+        text.push_str("null]");
+        text
+    }
+
+    #[test]
+    fn negotiates_supported_offers() {
+        let (params, header) = negotiate("permessage-deflate; client_max_window_bits").unwrap();
+        assert!(!params.server_no_context_takeover);
+        assert_eq!(header, "permessage-deflate");
+
+        let (params, header) =
+            negotiate("permessage-deflate; server_no_context_takeover; client_no_context_takeover").unwrap();
+        assert!(params.server_no_context_takeover);
+        assert_eq!(header, "permessage-deflate; server_no_context_takeover");
+
+        // A smaller server window can't be honoured, the next offer is taken
+        let (_, header) =
+            negotiate("permessage-deflate; server_max_window_bits=10, permessage-deflate; server_max_window_bits=15")
+                .unwrap();
+        assert_eq!(header, "permessage-deflate; server_max_window_bits=15");
+
+        assert!(negotiate("x-webkit-deflate-frame").is_none());
+        assert!(negotiate("permessage-deflate; server_max_window_bits=9").is_none());
+    }
+
+    #[tokio::test]
+    async fn passes_frames_through_without_the_extension() {
+        let (mut server, mut client, params) = connect("x-unknown", 1024 * 1024).await;
+        assert!(params.is_none());
+
+        client.write_all(&client_frame(true, false, 0x1, b"plain")).await.unwrap();
+        assert_eq!(next_text(&mut server).await, "plain");
+
+        let text = json_like(4096);
+        server.send(Message::Text(text.clone().into())).await.unwrap();
+        let (header, payload) = read_frame(&mut client).await;
+        assert!(!header.rsv1);
+        assert_eq!(payload, text.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn round_trips_compressed_messages() {
+        let (mut server, mut client, params) = connect("permessage-deflate", 1024 * 1024).await;
+        assert!(params.is_some());
+
+        // The client keeps its compression context across messages
+        let mut deflater = Compress::new(Compression::default(), false);
+        for text in [json_like(10_000), json_like(10_000), "short".to_string()] {
+            let compressed = compress(&mut deflater, text.as_bytes());
+            client.write_all(&client_frame(true, true, 0x1, &compressed)).await.unwrap();
+            assert_eq!(next_text(&mut server).await, text);
+        }
+
+        // Uncompressed frames are still accepted
+        client.write_all(&client_frame(true, false, 0x1, b"plain")).await.unwrap();
+        assert_eq!(next_text(&mut server).await, "plain");
+
+        // Large messages to the client are compressed, small ones are not
+        let mut inflater = Decompress::new(false);
+        let text = json_like(50_000);
+        server.send(Message::Text(text.clone().into())).await.unwrap();
+        let (header, payload) = read_frame(&mut client).await;
+        assert!(header.rsv1 && header.fin);
+        assert!(payload.len() < text.len() / 4);
+        assert_eq!(inflate(&mut inflater, &payload), text.as_bytes());
+
+        server.send(Message::Text("tiny".into())).await.unwrap();
+        let (header, payload) = read_frame(&mut client).await;
+        assert!(!header.rsv1);
+        assert_eq!(payload, b"tiny");
+    }
+
+    #[tokio::test]
+    async fn inflates_fragmented_messages_around_control_frames() {
+        let (mut server, mut client, _) = connect("permessage-deflate; client_no_context_takeover", 1024 * 1024).await;
+
+        let text = json_like(20_000);
+        let compressed = compress(&mut Compress::new(Compression::default(), false), text.as_bytes());
+        let (first, rest) = compressed.split_at(compressed.len() / 3);
+        let (second, third) = rest.split_at(rest.len() / 2);
+
+        // Only the first frame of a compressed message has RSV1 set, control
+        // frames may come in between and are never compressed
+        client.write_all(&client_frame(false, true, 0x1, first)).await.unwrap();
+        client.write_all(&client_frame(true, false, 0x9, b"ping")).await.unwrap();
+        client.write_all(&client_frame(false, false, 0x0, second)).await.unwrap();
+        client.write_all(&client_frame(true, false, 0x0, third)).await.unwrap();
+
+        match server.next().await {
+            Some(Ok(Message::Ping(payload))) => assert_eq!(&payload[..], b"ping"),
+            other => panic!("Expected a ping, got {:?}", other),
+        }
+        assert_eq!(next_text(&mut server).await, text);
+
+        // With client_no_context_takeover, every message starts from a new context
+        let text = json_like(5_000);
+        let compressed = compress(&mut Compress::new(Compression::default(), false), text.as_bytes());
+        client.write_all(&client_frame(true, true, 0x2, &compressed)).await.unwrap();
+        match server.next().await {
+            Some(Ok(Message::Binary(payload))) => assert_eq!(&payload[..], text.as_bytes()),
+            other => panic!("Expected a binary message, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn compresses_each_message_on_its_own_without_server_context_takeover() {
+        let (mut server, mut client, params) = connect("permessage-deflate; server_no_context_takeover", 1024 * 1024).await;
+        assert!(params.unwrap().server_no_context_takeover);
+
+        let text = json_like(8_000);
+        for _ in 0..2 {
+            server.send(Message::Text(text.clone().into())).await.unwrap();
+            let (header, payload) = read_frame(&mut client).await;
+            assert!(header.rsv1);
+            assert_eq!(inflate(&mut Decompress::new(false), &payload), text.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_the_server_context_by_default() {
+        let (mut server, mut client, _) = connect("permessage-deflate", 1024 * 1024).await;
+
+        let text = json_like(8_000);
+        let mut inflater = Decompress::new(false);
+        let mut sizes = Vec::new();
+        for _ in 0..2 {
+            server.send(Message::Text(text.clone().into())).await.unwrap();
+            let (_, payload) = read_frame(&mut client).await;
+            sizes.push(payload.len());
+            assert_eq!(inflate(&mut inflater, &payload), text.as_bytes());
+        }
+        // The second copy refers back to the first one
+        assert!(sizes[1] < sizes[0] / 4);
+    }
+
+    #[tokio::test]
+    async fn rejects_messages_that_inflate_past_the_limit() {
+        let max_message_size = 1024 * 1024;
+        let (mut server, mut client, _) = connect("permessage-deflate", max_message_size).await;
+
+        let bomb = compress(&mut Compress::new(Compression::best(), false), &vec![b'0'; 16 * max_message_size]);
+        assert!(bomb.len() < max_message_size / 4);
+        client.write_all(&client_frame(true, true, 0x1, &bomb)).await.unwrap();
+
+        match server.next().await {
+            Some(Err(tokio_tungstenite::tungstenite::Error::Io(e))) => {
+                assert_eq!(e.kind(), io::ErrorKind::InvalidData)
+            }
+            other => panic!("Expected the message to be rejected, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_fragmented_messages_that_inflate_past_the_limit() {
+        let max_message_size = 64 * 1024;
+        let (mut server, mut client, _) = connect("permessage-deflate", max_message_size).await;
+
+        // Each fragment is under the limit, all of them together are not
+        let mut deflater = Compress::new(Compression::default(), false);
+        let fragment = vec![b'1'; max_message_size / 2];
+        client
+            .write_all(&client_frame(false, true, 0x1, &compress(&mut deflater, &fragment)))
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            client
+                .write_all(&client_frame(false, false, 0x0, &compress(&mut deflater, &fragment)))
+                .await
+                .unwrap();
+        }
+
+        assert!(matches!(server.next().await, Some(Err(_))));
+    }
+
+    #[tokio::test]
+    async fn rejects_oversized_frames_from_their_header() {
+        for extensions in ["permessage-deflate", "x-unknown"] {
+            let (mut server, mut client, _) = connect_with_limits(extensions, 1024, 64 * 1024).await;
+
+            // Only the header is sent, the frame is refused without waiting for its payload
+            let mut header = Vec::new();
+            write_header(&mut header, true, false, 0x1, Some(MASK), 4096);
+            client.write_all(&header).await.unwrap();
+
+            match server.next().await {
+                Some(Err(tokio_tungstenite::tungstenite::Error::Io(e))) => {
+                    assert_eq!(e.kind(), io::ErrorKind::InvalidData)
+                }
+                other => panic!("Expected the frame to be rejected, got {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_frames_that_inflate_past_the_frame_limit() {
+        let (mut server, mut client, _) = connect_with_limits("permessage-deflate", 4 * 1024, 64 * 1024).await;
+
+        let payload = compress(&mut Compress::new(Compression::default(), false), &[b'2'; 16 * 1024]);
+        assert!(payload.len() < 1024);
+        client.write_all(&client_frame(true, true, 0x1, &payload)).await.unwrap();
+
+        assert!(matches!(server.next().await, Some(Err(_))));
+    }
+}
@@ -0,0 +1,322 @@
+use crate::reactauri_core_server::Command;
+use crate::server_metrics;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+use tauri::Emitter;
+
+// How often a `clientThrottled` summary is emitted while a client is throttled
+pub const SUMMARY_INTERVAL: Duration = Duration::from_secs(1);
+
+// Commands that are never throttled, along with every `*.response`: dropping
+// them would break the connection or leave a request unanswered
+const EXEMPT_TYPES: [&str; 3] = ["client.intro", "customCommand.register", "customCommand.unregister"];
+
+fn is_exempt(command_type: &str) -> bool {
+    EXEMPT_TYPES.contains(&command_type) || command_type.ends_with(".response")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OverflowAction {
+    // Discard excess commands
+    Drop,
+    // Let one of every `sample_every` excess commands through
+    Sample,
+    // Collapse excess commands into one per type, carrying the count
+    Aggregate,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimit {
+    // Sustained commands per second
+    pub rate: f64,
+    // Commands allowed in a burst above the sustained rate
+    pub burst: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitOptions {
+    pub per_client: RateLimit,
+    #[serde(default)]
+    pub per_type: HashMap<String, RateLimit>,
+    pub overflow: OverflowAction,
+    #[serde(default = "default_sample_every")]
+    pub sample_every: u64,
+}
+
+fn default_sample_every() -> u64 {
+    100
+}
+
+impl Default for RateLimitOptions {
+    fn default() -> Self {
+        Self {
+            per_client: RateLimit {
+                rate: 1000.0,
+                burst: 2000.0,
+            },
+            per_type: HashMap::from([(
+                "log".to_string(),
+                RateLimit {
+                    rate: 500.0,
+                    burst: 1000.0,
+                },
+            )]),
+            overflow: OverflowAction::Aggregate,
+            sample_every: default_sample_every(),
+        }
+    }
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+    limit: RateLimit,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            tokens: limit.burst,
+            last_refill: Instant::now(),
+            limit,
+        }
+    }
+
+    fn try_take(&mut self, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst);
+        self.last_refill = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThrottleSummary {
+    pub connection_id: u32,
+    pub client_id: Option<String>,
+    pub action: OverflowAction,
+    pub since: String,
+    pub until: String,
+    // Excess commands per type that did not reach the timeline as-is
+    pub dropped: HashMap<String, u64>,
+    // Excess commands per type let through by sampling
+    pub sampled: HashMap<String, u64>,
+    pub total_dropped: u64,
+    #[serde(skip)]
+    pub aggregated: Vec<Command>,
+}
+
+// Token-bucket limiter for a single connection, owned by its receive loop
+pub struct ClientRateLimiter {
+    options: RateLimitOptions,
+    client_bucket: TokenBucket,
+    type_buckets: HashMap<String, TokenBucket>,
+    excess_counts: HashMap<String, u64>,
+    dropped: HashMap<String, u64>,
+    sampled: HashMap<String, u64>,
+    aggregated: HashMap<String, Command>,
+    window_start: chrono::DateTime<chrono::Utc>,
+}
+
+impl ClientRateLimiter {
+    pub fn new(options: RateLimitOptions) -> Self {
+        Self {
+            client_bucket: TokenBucket::new(options.per_client.clone()),
+            options,
+            type_buckets: HashMap::new(),
+            excess_counts: HashMap::new(),
+            dropped: HashMap::new(),
+            sampled: HashMap::new(),
+            aggregated: HashMap::new(),
+            window_start: chrono::Utc::now(),
+        }
+    }
+
+    // Returns the command if it may go through, or keeps track of it as excess
+    pub fn admit(&mut self, cmd: Command) -> Option<Command> {
+        if is_exempt(&cmd.r#type) {
+            return Some(cmd);
+        }
+
+        let now = Instant::now();
+        let type_allowed = match self.options.per_type.get(&cmd.r#type) {
+            Some(limit) => self
+                .type_buckets
+                .entry(cmd.r#type.clone())
+                .or_insert_with(|| TokenBucket::new(limit.clone()))
+                .try_take(now),
+            None => true,
+        };
+        if type_allowed && self.client_bucket.try_take(now) {
+            return Some(cmd);
+        }
+
+        let excess = self.excess_counts.entry(cmd.r#type.clone()).or_insert(0);
+        *excess += 1;
+
+        match self.options.overflow {
+            OverflowAction::Sample if excess.is_multiple_of(self.options.sample_every.max(1)) => {
+                *self.sampled.entry(cmd.r#type.clone()).or_insert(0) += 1;
+                Some(cmd)
+            }
+            OverflowAction::Aggregate => {
+                *self.dropped.entry(cmd.r#type.clone()).or_insert(0) += 1;
+                self.aggregated.insert(cmd.r#type.clone(), cmd);
+                None
+            }
+            _ => {
+                *self.dropped.entry(cmd.r#type.clone()).or_insert(0) += 1;
+                None
+            }
+        }
+    }
+
+    // Summary of what was throttled since the last one, taken every `SUMMARY_INTERVAL`
+    pub fn take_summary(&mut self, connection_id: u32, client_id: Option<String>) -> Option<ThrottleSummary> {
+        let since = std::mem::replace(&mut self.window_start, chrono::Utc::now());
+        self.excess_counts.clear();
+
+        if self.dropped.is_empty() && self.sampled.is_empty() {
+            return None;
+        }
+
+        let dropped = std::mem::take(&mut self.dropped);
+        let aggregated = self
+            .aggregated
+            .drain()
+            .map(|(command_type, mut cmd)| {
+                cmd.aggregated_count = dropped.get(&command_type).copied();
+                cmd
+            })
+            .collect();
+
+        Some(ThrottleSummary {
+            connection_id,
+            client_id,
+            action: self.options.overflow,
+            since: since.to_rfc3339(),
+            until: self.window_start.to_rfc3339(),
+            total_dropped: dropped.values().sum(),
+            dropped,
+            sampled: std::mem::take(&mut self.sampled),
+            aggregated,
+        })
+    }
+}
+
+// Emit a `clientThrottled` event, returning the aggregated commands for the timeline
+pub fn report(app_handle: &AppHandle, mut summary: ThrottleSummary) -> Vec<Command> {
+    server_metrics::record_throttled(summary.connection_id, summary.total_dropped);
+    let _ = app_handle.emit("clientThrottled", &summary);
+    std::mem::take(&mut summary.aggregated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(command_type: &str) -> Command {
+        serde_json::from_value(serde_json::json!({ "type": command_type, "payload": { "n": 1 } })).unwrap()
+    }
+
+    fn options(overflow: OverflowAction) -> RateLimitOptions {
+        RateLimitOptions {
+            per_client: RateLimit { rate: 0.0, burst: 3.0 },
+            per_type: HashMap::new(),
+            overflow,
+            sample_every: 2,
+        }
+    }
+
+    #[test]
+    fn bucket_allows_a_burst_then_refills_at_the_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new(RateLimit { rate: 10.0, burst: 2.0 });
+
+        assert!(bucket.try_take(start));
+        assert!(bucket.try_take(start));
+        assert!(!bucket.try_take(start));
+
+        // 10 per second is one token every 100 ms
+        assert!(!bucket.try_take(start + Duration::from_millis(50)));
+        assert!(bucket.try_take(start + Duration::from_millis(110)));
+        assert!(!bucket.try_take(start + Duration::from_millis(110)));
+
+        // Never more than the burst, however long it has been
+        let later = start + Duration::from_secs(60);
+        assert!(bucket.try_take(later));
+        assert!(bucket.try_take(later));
+        assert!(!bucket.try_take(later));
+    }
+
+    #[test]
+    fn per_type_limits_apply_on_top_of_the_client_limit() {
+        let mut options = options(OverflowAction::Drop);
+        options.per_client.burst = 100.0;
+        options.per_type.insert("log".to_string(), RateLimit { rate: 0.0, burst: 1.0 });
+        let mut limiter = ClientRateLimiter::new(options);
+
+        assert!(limiter.admit(command("log")).is_some());
+        assert!(limiter.admit(command("log")).is_none());
+        assert!(limiter.admit(command("display")).is_some());
+    }
+
+    #[test]
+    fn aggregates_excess_commands() {
+        let mut limiter = ClientRateLimiter::new(options(OverflowAction::Aggregate));
+        for _ in 0..3 {
+            assert!(limiter.admit(command("log")).is_some());
+        }
+        for _ in 0..5 {
+            assert!(limiter.admit(command("log")).is_none());
+        }
+        assert!(limiter.admit(command("display")).is_none());
+
+        let summary = limiter.take_summary(1, Some("client".to_string())).unwrap();
+        assert_eq!(summary.total_dropped, 6);
+        assert_eq!(summary.dropped.get("log"), Some(&5));
+        let log = summary.aggregated.iter().find(|c| c.r#type == "log").unwrap();
+        assert_eq!(log.aggregated_count, Some(5));
+
+        // Nothing new was throttled since
+        assert!(limiter.take_summary(1, None).is_none());
+    }
+
+    #[test]
+    fn samples_excess_commands() {
+        let mut limiter = ClientRateLimiter::new(options(OverflowAction::Sample));
+        for _ in 0..3 {
+            limiter.admit(command("log"));
+        }
+        let admitted = (0..6).filter(|_| limiter.admit(command("log")).is_some()).count();
+        assert_eq!(admitted, 3);
+
+        let summary = limiter.take_summary(1, None).unwrap();
+        assert_eq!(summary.sampled.get("log"), Some(&3));
+        assert_eq!(summary.total_dropped, 3);
+    }
+
+    #[test]
+    fn never_throttles_intros_responses_and_registrations() {
+        let mut limiter = ClientRateLimiter::new(options(OverflowAction::Drop));
+        for _ in 0..3 {
+            limiter.admit(command("log"));
+        }
+        assert!(limiter.admit(command("log")).is_none());
+
+        for command_type in ["client.intro", "state.values.response", "state.backup.response", "customCommand.register"] {
+            assert!(limiter.admit(command(command_type)).is_some(), "{}", command_type);
+        }
+    }
+}
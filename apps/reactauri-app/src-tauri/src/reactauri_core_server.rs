@@ -1,11 +1,18 @@
+use futures_util::stream::SplitSink;
 use futures_util::{SinkExt, StreamExt};
+use log::{debug, error, info, trace, warn};
 use serde::{Deserialize, Serialize};
 use tauri::async_runtime;
 use tauri::AppHandle;
 use tauri::Emitter;
 use tauri::Listener;
+use tauri_plugin_store::StoreExt;
 use tokio::net::TcpListener;
-use tokio_tungstenite::accept_async;
+use tokio_tungstenite::accept_hdr_async_with_config;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::error::{CapacityError, Error as WsError};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, WebSocketConfig};
 use tokio_tungstenite::tungstenite::Message;
 use std::sync::{Arc, Mutex};
 use tokio::sync::Mutex as TokioMutex;
@@ -14,6 +21,26 @@ use uuid::Uuid;
 use chrono;
 use std::time::Duration;
 use tokio::time::interval;
+use tokio::sync::mpsc;
+use crate::action_scripts;
+use crate::api_analytics;
+use crate::benchmarks;
+use crate::chunked_messages::{self, ChunkAssembler};
+use crate::client_requests;
+use crate::command_batcher;
+use crate::custom_commands;
+use crate::mirror_mode;
+use crate::parse_errors::{self, CommandParseError, ParseError};
+use crate::payload_store;
+use crate::payload_validation::{self, ValidationWarning};
+use crate::permessage_deflate::{self, CompressionOptions, DeflateStream, Negotiation};
+use crate::rate_limiter::{self, ClientRateLimiter, RateLimitOptions};
+use crate::redaction::{self, CommandRedaction};
+use crate::server_metrics;
+use crate::snapshot_library;
+use crate::subscription_cache;
+use crate::time_travel::{self, TimeTravelOptions};
+use crate::wire_encoding::WireEncoding;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -32,6 +59,18 @@ pub struct Command {
     pub delta_time: Option<serde_json::Value>,
     #[serde(default, rename = "clientId")]
     pub client_id: Option<String>,
+    // Set when the payload was offloaded, see `payload_store::get_payload`
+    #[serde(default, rename = "payloadHandle", skip_serializing_if = "Option::is_none")]
+    pub payload_handle: Option<String>,
+    // Set on a command standing in for this many throttled commands of its type
+    #[serde(default, rename = "aggregatedCount", skip_serializing_if = "Option::is_none")]
+    pub aggregated_count: Option<u64>,
+    // Set when the payload does not match the contract, see `payload_validation`
+    #[serde(default, rename = "validationWarnings", skip_serializing_if = "Option::is_none")]
+    pub validation_warnings: Option<Vec<ValidationWarning>>,
+    // Set when redaction rules replaced parts of the payload, see `redaction`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redaction: Option<CommandRedaction>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,13 +86,68 @@ pub struct CommandWithClientId {
     pub delta_time: Option<i64>,
 }
 
+// Outbound half of a client websocket. Messages are queued and written by a
+// dedicated task, so senders never wait on the connection's read loop.
+#[derive(Debug, Clone)]
+pub struct ClientSocket {
+    connection_id: u32,
+    sender: mpsc::UnboundedSender<Message>,
+}
+
+impl ClientSocket {
+    fn new(
+        connection_id: u32,
+        mut sink: SplitSink<ClientStream, Message>,
+    ) -> Self {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<Message>();
+
+        async_runtime::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                server_metrics::message_dequeued(connection_id);
+                if let Err(e) = sink.send(message).await {
+                    warn!("Error writing to connection {}: {}", connection_id, e);
+                    break;
+                }
+            }
+            let _ = sink.close().await;
+        });
+
+        Self { connection_id, sender }
+    }
+
+    pub fn send(&self, message: Message) -> Result<(), String> {
+        server_metrics::message_queued(self.connection_id);
+        self.sender.send(message).map_err(|_| {
+            server_metrics::message_dequeued(self.connection_id);
+            format!("Connection {} is closed", self.connection_id)
+        })
+    }
+
+    pub fn send_text(&self, command_type: &str, text: String) -> Result<(), String> {
+        server_metrics::record_outgoing(self.connection_id, command_type, text.len());
+        self.send(Message::Text(text.into()))
+    }
+
+    pub fn send_encoded(&self, command_type: &str, value: &serde_json::Value, encoding: WireEncoding) -> Result<(), String> {
+        if encoding == WireEncoding::Json {
+            return self.send_text(command_type, value.to_string());
+        }
+        let bytes = encoding.encode(value)?;
+        server_metrics::record_outgoing(self.connection_id, command_type, bytes.len());
+        self.send(Message::Binary(bytes.into()))
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct ClientConnection {
     pub id: u32,
     pub address: String,
     pub client_id: String,
+    // App name from `client.intro`
+    pub name: Option<String>,
+    pub encoding: WireEncoding,
     #[serde(skip)]
-    pub socket: Arc<TokioMutex<tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>>>,
+    pub socket: ClientSocket,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -61,10 +155,11 @@ pub struct PartialConnection {
     pub id: u32,
     pub address: String,
     #[serde(skip)]
-    pub socket: Arc<TokioMutex<tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>>>,
+    pub socket: ClientSocket,
 }
 
 type ServerHandle = Arc<Mutex<Option<tauri::async_runtime::JoinHandle<()>>>>;
+type ClientStream = tokio_tungstenite::WebSocketStream<DeflateStream<tokio::net::TcpStream>>;
 type WsStream = Arc<TokioMutex<Option<tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>>>>;
 type ClientConnections = Arc<TokioMutex<HashMap<String, ClientConnection>>>;
 type Subscriptions = Arc<TokioMutex<Vec<String>>>;
@@ -78,11 +173,85 @@ static mut SUBSCRIPTIONS: Option<Subscriptions> = None;
 static mut PARTIAL_CONNECTIONS: Option<PartialConnections> = None;
 static mut SERVER_STATE: Option<ServerStateHandle> = None;
 
+const OPTIONS_STORE: &str = "server-options.json";
+const OPTIONS_KEY: &str = "options";
+
 // Server configuration options
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerOptions {
     pub port: u16,
     pub wss: Option<WssServerOptions>,
+    // How often the `metrics` event is emitted
+    #[serde(default = "default_metrics_interval_ms")]
+    pub metrics_interval_ms: u64,
+    // Serve Prometheus metrics on this local port when set
+    #[serde(default)]
+    pub metrics_port: Option<u16>,
+    // Commands arriving faster than this are batched into a `commands` event
+    #[serde(default = "default_batch_interval_ms")]
+    pub batch_interval_ms: u64,
+    #[serde(default = "default_max_batch_size")]
+    pub max_batch_size: usize,
+    // Payloads larger than this many bytes are kept server-side, 0 disables.
+    // The timeline fetches them with `payload_store::get_payload` when opened.
+    #[serde(default = "default_payload_offload_threshold")]
+    pub payload_offload_threshold: usize,
+    // Flood protection, disabled when None
+    #[serde(default = "default_rate_limit")]
+    pub rate_limit: Option<RateLimitOptions>,
+    // Largest websocket frame and message accepted from a client, in bytes
+    #[serde(default = "default_max_frame_size")]
+    pub max_frame_size: usize,
+    #[serde(default = "default_max_message_size")]
+    pub max_message_size: usize,
+    // Most bytes of chunked messages a client may have in flight at once,
+    // and so the largest message reassembled, see `chunked_messages`
+    #[serde(default = "default_max_chunked_message_size")]
+    pub max_chunked_message_size: usize,
+    // permessage-deflate, used when the client offers it
+    #[serde(default)]
+    pub compression: CompressionOptions,
+    // Tell clients about commands that could not be parsed, as a `commandParseError` command
+    #[serde(default)]
+    pub send_parse_errors_to_client: bool,
+    // Check payloads against the contract schemas, flagging commands that do not match
+    #[serde(default)]
+    pub validate_payloads: bool,
+    // Keep the actions and periodic backups of each client to restore it to any action
+    #[serde(default)]
+    pub time_travel: TimeTravelOptions,
+}
+
+fn default_metrics_interval_ms() -> u64 {
+    2000
+}
+
+fn default_batch_interval_ms() -> u64 {
+    16
+}
+
+fn default_max_batch_size() -> usize {
+    200
+}
+
+fn default_payload_offload_threshold() -> usize {
+    512 * 1024
+}
+
+fn default_rate_limit() -> Option<RateLimitOptions> {
+    Some(RateLimitOptions::default())
+}
+
+fn default_max_frame_size() -> usize {
+    16 * 1024 * 1024
+}
+
+fn default_max_message_size() -> usize {
+    64 * 1024 * 1024
+}
+
+fn default_max_chunked_message_size() -> usize {
+    256 * 1024 * 1024
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -98,6 +267,19 @@ impl Default for ServerOptions {
         Self {
             port: 9090,
             wss: None,
+            metrics_interval_ms: default_metrics_interval_ms(),
+            metrics_port: None,
+            batch_interval_ms: default_batch_interval_ms(),
+            max_batch_size: default_max_batch_size(),
+            payload_offload_threshold: default_payload_offload_threshold(),
+            rate_limit: default_rate_limit(),
+            max_frame_size: default_max_frame_size(),
+            max_message_size: default_max_message_size(),
+            max_chunked_message_size: default_max_chunked_message_size(),
+            compression: CompressionOptions::default(),
+            send_parse_errors_to_client: false,
+            validate_payloads: false,
+            time_travel: TimeTravelOptions::default(),
         }
     }
 }
@@ -108,6 +290,10 @@ pub struct ServerState {
     pub started: bool,
     pub options: ServerOptions,
     pub keep_alive_handle: Arc<TokioMutex<Option<tauri::async_runtime::JoinHandle<()>>>>,
+    pub metrics_handle: Arc<TokioMutex<Option<tauri::async_runtime::JoinHandle<()>>>>,
+    pub prometheus_handle: Arc<TokioMutex<Option<tauri::async_runtime::JoinHandle<()>>>>,
+    pub batch_handle: Arc<TokioMutex<Option<tauri::async_runtime::JoinHandle<()>>>>,
+    pub snapshotter_handle: Arc<TokioMutex<Option<tauri::async_runtime::JoinHandle<()>>>>,
 }
 
 impl Default for ServerState {
@@ -116,6 +302,10 @@ impl Default for ServerState {
             started: false,
             options: ServerOptions::default(),
             keep_alive_handle: Arc::new(TokioMutex::new(None)),
+            metrics_handle: Arc::new(TokioMutex::new(None)),
+            prometheus_handle: Arc::new(TokioMutex::new(None)),
+            batch_handle: Arc::new(TokioMutex::new(None)),
+            snapshotter_handle: Arc::new(TokioMutex::new(None)),
         }
     }
 }
@@ -176,11 +366,87 @@ pub fn get_server_state() -> &'static ServerStateHandle {
 
 // Configure server options
 pub async fn configure_server(options: ServerOptions) {
+    payload_validation::set_enabled(options.validate_payloads);
     let server_state = get_server_state();
     let mut state = server_state.lock().await;
     state.options = options;
 }
 
+// Restore the options saved from a previous session, before the server starts
+pub fn init_options(app_handle: &AppHandle) {
+    let saved = app_handle
+        .store(OPTIONS_STORE)
+        .ok()
+        .and_then(|store| store.get(OPTIONS_KEY))
+        .and_then(|value| serde_json::from_value::<ServerOptions>(value).ok());
+    if let Some(options) = saved {
+        payload_validation::set_enabled(options.validate_payloads);
+        get_server_state().blocking_lock().options = options;
+    }
+}
+
+pub fn get_options() -> ServerOptions {
+    get_server_state().blocking_lock().options.clone()
+}
+
+fn save_options(app_handle: &AppHandle, options: &ServerOptions) -> Result<(), String> {
+    let store = app_handle
+        .store(OPTIONS_STORE)
+        .map_err(|e| format!("Failed to open server options: {}", e))?;
+    store.set(OPTIONS_KEY, serde_json::to_value(options).map_err(|e| e.to_string())?);
+    store
+        .save()
+        .map_err(|e| format!("Failed to save server options: {}", e))
+}
+
+// Save and apply new options, restarting the server when it is running
+pub fn set_options(app_handle: AppHandle, options: ServerOptions) -> Result<ServerOptions, String> {
+    save_options(&app_handle, &options)?;
+
+    let started = {
+        let mut state = get_server_state().blocking_lock();
+        state.options = options.clone();
+        state.started
+    };
+    payload_validation::set_enabled(options.validate_payloads);
+    if started {
+        start_server(app_handle);
+    }
+    Ok(options)
+}
+
+// Move the Prometheus endpoint to another port, or stop it, leaving clients connected
+pub fn set_metrics_port(app_handle: AppHandle, metrics_port: Option<u16>) -> Result<ServerOptions, String> {
+    let mut state = get_server_state().blocking_lock();
+    if metrics_port.is_some_and(|port| port == state.options.port) {
+        return Err(format!("Port {} is used by the server itself", state.options.port));
+    }
+    let mut options = state.options.clone();
+    options.metrics_port = metrics_port;
+    save_options(&app_handle, &options)?;
+    state.options = options.clone();
+
+    let mut handle_guard = state.prometheus_handle.blocking_lock();
+    if let Some(existing_handle) = handle_guard.take() {
+        existing_handle.abort();
+    }
+    if state.started {
+        *handle_guard = metrics_port.map(server_metrics::start_prometheus_endpoint);
+    }
+    Ok(options)
+}
+
+// Turn payload validation on or off, applying to connected clients straight away
+pub fn set_validate_payloads(app_handle: AppHandle, validate_payloads: bool) -> Result<ServerOptions, String> {
+    let mut state = get_server_state().blocking_lock();
+    let mut options = state.options.clone();
+    options.validate_payloads = validate_payloads;
+    save_options(&app_handle, &options)?;
+    state.options = options.clone();
+    payload_validation::set_enabled(validate_payloads);
+    Ok(options)
+}
+
 // Check if server is started
 pub async fn is_server_started() -> bool {
     let server_state = get_server_state();
@@ -196,7 +462,7 @@ pub fn start_server(app_handle: AppHandle) {
     {
         let mut guard = server_handle.lock().unwrap();
         if let Some(handle) = guard.take() {
-            println!("Stopping existing server");
+            info!("Stopping existing server");
             handle.abort();
             app_handle.emit("stop", "stop").unwrap();
         }
@@ -212,10 +478,11 @@ pub fn start_server(app_handle: AppHandle) {
     let handle = async_runtime::spawn(async move {
         // Get server options
         let server_state = get_server_state();
-        let port = {
+        let options = {
             let state = server_state.lock().await;
-            state.options.port
+            state.options.clone()
         };
+        let port = options.port;
         
         let listener = match TcpListener::bind(format!("0.0.0.0:{}", port)).await {
             Ok(listener) => listener,
@@ -227,12 +494,12 @@ pub fn start_server(app_handle: AppHandle) {
                     }
                     app_handle.emit("portUnavailable", port).unwrap();
                 } else {
-                    println!("Error starting server: {}", e);
+                    error!("Error starting server: {}", e);
                 }
                 return;
             }
         };
-        println!("WebSocket server started: ws://0.0.0.0:{}", port);
+        info!("WebSocket server started: ws://0.0.0.0:{}", port);
         
         
         // Store keep alive handle
@@ -249,6 +516,57 @@ pub fn start_server(app_handle: AppHandle) {
             *handle_guard = Some(keep_alive_handle);
         }
 
+        // Start metrics reporting
+        {
+            let state = server_state.lock().await;
+            let mut handle_guard = state.metrics_handle.lock().await;
+
+            if let Some(existing_handle) = handle_guard.take() {
+                existing_handle.abort();
+            }
+
+            *handle_guard = Some(server_metrics::start_reporter(
+                app_handle.clone(),
+                Duration::from_millis(options.metrics_interval_ms.max(100)),
+            ));
+
+            let mut handle_guard = state.prometheus_handle.lock().await;
+
+            if let Some(existing_handle) = handle_guard.take() {
+                existing_handle.abort();
+            }
+
+            *handle_guard = options.metrics_port.map(server_metrics::start_prometheus_endpoint);
+        }
+
+        // Start command batching
+        {
+            let state = server_state.lock().await;
+            let mut handle_guard = state.batch_handle.lock().await;
+
+            if let Some(existing_handle) = handle_guard.take() {
+                existing_handle.abort();
+            }
+
+            *handle_guard = Some(command_batcher::start_flusher(
+                app_handle.clone(),
+                Duration::from_millis(options.batch_interval_ms.max(1)),
+                options.max_batch_size,
+            ));
+        }
+
+        // Start time travel snapshots
+        {
+            let state = server_state.lock().await;
+            let mut handle_guard = state.snapshotter_handle.lock().await;
+
+            if let Some(existing_handle) = handle_guard.take() {
+                existing_handle.abort();
+            }
+
+            *handle_guard = Some(time_travel::start_snapshotter(app_handle.clone(), options.time_travel.clone()));
+        }
+
         let mut connection_id = 0;
         let mut message_id = 0;
 
@@ -265,6 +583,7 @@ pub fn start_server(app_handle: AppHandle) {
             let app_handle = app_handle.clone();
             let current_connection_id = connection_id;
             connection_id += 1;
+            let options = options.clone();
 
             async_runtime::spawn(async move {
                 let ws_stream = get_ws_stream();
@@ -272,15 +591,41 @@ pub fn start_server(app_handle: AppHandle) {
                 let subscriptions = get_subscriptions();
                 let partial_connections = get_partial_connections();
                 
-                let ws = accept_async(stream).await.unwrap();
-                let ws = Arc::new(TokioMutex::new(ws));
-                println!("WebSocket connection accepted from {}", format_address(&addr));
+                let ws_config = WebSocketConfig::default()
+                    .max_frame_size(Some(options.max_frame_size))
+                    .max_message_size(Some(options.max_message_size));
+                let negotiation = Negotiation::default();
+                let stream = DeflateStream::new(
+                    stream,
+                    current_connection_id,
+                    negotiation.clone(),
+                    &options.compression,
+                    options.max_frame_size,
+                    options.max_message_size,
+                );
+                // The callback signature is fixed by tungstenite
+                #[allow(clippy::result_large_err)]
+                let negotiate = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+                    if !options.compression.enabled {
+                        return Ok(response);
+                    }
+                    Ok(permessage_deflate::negotiate_handshake(request, response, &negotiation))
+                };
+                let ws = accept_hdr_async_with_config(stream, negotiate, Some(ws_config)).await.unwrap();
+                let (sink, mut stream) = ws.split();
+                let socket = ClientSocket::new(current_connection_id, sink);
+                info!("WebSocket connection accepted from {}", format_address(&addr));
+                server_metrics::connection_opened(current_connection_id, format_address(&addr));
+                if negotiation.lock().unwrap().is_some() {
+                    debug!("permessage-deflate negotiated with {}", format_address(&addr));
+                    server_metrics::compression_negotiated(current_connection_id);
+                }
 
                 // Create and store partialConnection
                 let partial_connection = PartialConnection {
                     id: current_connection_id,
                     address: format_address(&addr),
-                    socket: ws.clone(),
+                    socket: socket.clone(),
                 };
 
                 // Add to partialConnections
@@ -293,168 +638,331 @@ pub fn start_server(app_handle: AppHandle) {
                 app_handle.emit("connect", &partial_connection).unwrap();
 
                 let mut current_client_id = None;
+                let mut rate_limiter = options.rate_limit.clone().map(ClientRateLimiter::new);
+                let mut chunk_assembler = ChunkAssembler::new(options.max_chunked_message_size);
+                let mut encoding = WireEncoding::Json;
+
+                let mut summary_interval = interval(rate_limiter::SUMMARY_INTERVAL);
 
                 loop {
-                    let mut ws_guard = ws.lock().await;
-                    if let Some(msg) = ws_guard.next().await {
-                        let msg = msg.unwrap();
-                        if msg.is_text() {
-                            let text = msg.to_text().unwrap();
-                            println!("Received text: {}", text);
-                            
-                            if let Ok(mut cmd) = serde_json::from_str::<Command>(text) {
-                                message_id += 1;
-                                cmd.message_id = Some(message_id);
-                                cmd.connection_id = Some(current_connection_id);
-
-                                println!("=== New client connection ===");
-                                println!("Connection ID: {}", current_connection_id);
-
-                                // Handle client.intro
-                                if cmd.r#type == "client.intro" {
-                                    println!("=== Processing client.intro ===");
-                                    println!("Client ID: {:?}", cmd.payload.get("clientId"));
-                                    println!("Payload: {:?}", cmd.payload);
-
-                                    // Find partialConnection
-                                    let mut partials = partial_connections.lock().await;
-                                    let part_conn_opt = partials.iter().find(|c| c.id == current_connection_id).cloned();
-
-                                    // Add address to payload
-                                    if let Some(part_conn) = &part_conn_opt {
-                                        if let Some(payload) = cmd.payload.as_object_mut() {
-                                            payload.insert("address".to_string(), serde_json::Value::String(part_conn.address.clone()));
-                                        }
-                                    }
+                    let msg = tokio::select! {
+                        msg = stream.next() => match msg {
+                            Some(msg) => msg,
+                            None => break,
+                        },
+                        // Report throttling even once the client has gone quiet
+                        _ = summary_interval.tick() => {
+                            if let Some(summary) = rate_limiter
+                                .as_mut()
+                                .and_then(|limiter| limiter.take_summary(current_connection_id, current_client_id.clone()))
+                            {
+                                for cmd in rate_limiter::report(&app_handle, summary) {
+                                    emit_command(&app_handle, cmd, options.payload_offload_threshold);
+                                }
+                            }
+                            continue;
+                        }
+                    };
+                    let msg = match msg {
+                        Ok(msg) => msg,
+                        Err(WsError::Capacity(CapacityError::MessageTooLong { size, max_size })) => {
+                            warn!(
+                                "Closing connection {}: message of {} bytes exceeds the limit of {} bytes",
+                                current_connection_id, size, max_size
+                            );
+                            let _ = socket.send(Message::Close(Some(CloseFrame {
+                                code: CloseCode::Size,
+                                reason: format!("Message too big: {} > {} bytes, send it in chunks", size, max_size).into(),
+                            })));
+                            break;
+                        }
+                        // Raised by `DeflateStream` for oversized frames and messages that inflate past the limits
+                        Err(WsError::Io(e)) if e.kind() == std::io::ErrorKind::InvalidData => {
+                            warn!("Closing connection {}: {}", current_connection_id, e);
+                            let _ = socket.send(Message::Close(Some(CloseFrame {
+                                code: CloseCode::Size,
+                                reason: e.to_string().into(),
+                            })));
+                            break;
+                        }
+                        Err(e) => {
+                            warn!("Error reading from connection {}: {}", current_connection_id, e);
+                            break;
+                        }
+                    };
+                    if msg.is_pong() {
+                        server_metrics::record_pong(current_connection_id);
+                    }
+                    let (message_encoding, mut parsed, mut raw): (_, _, &[u8]) = match &msg {
+                        Message::Text(text) => {
+                            if redaction::is_active() {
+                                trace!("Received {} text bytes", text.len());
+                            } else {
+                                trace!("Received text: {}", text);
+                            }
+                            let parsed = serde_json::from_str::<Command>(text).map_err(ParseError::from);
+                            (WireEncoding::Json, parsed, text.as_bytes())
+                        }
+                        Message::Binary(bytes) => {
+                            trace!("Received {} binary bytes", bytes.len());
+                            let frame_encoding = match encoding {
+                                WireEncoding::Json => WireEncoding::sniff(bytes),
+                                negotiated => negotiated,
+                            };
+                            (frame_encoding, frame_encoding.decode(bytes), bytes)
+                        }
+                        _ => continue,
+                    };
 
-                                    // Remove from partialConnections
-                                    partials.retain(|c| c.id != current_connection_id);
-
-                                    // Handle clientId
-                                    let mut client_id = cmd.payload.get("clientId").and_then(|v| v.as_str()).map(|s| s.to_string());
-                                    println!("client_id: {:?}", client_id);
-                                    if client_id.is_none() || client_id.as_ref().map_or(false, |id| id == "~~~ null ~~~") {
-                                        println!("No clientId found, generating new one");
-                                        client_id = Some(Uuid::new_v4().to_string());
-                                        // Send clientId to client
-                                        let response = serde_json::json!({
-                                            "type": "setClientId",
-                                            "payload": client_id.as_ref().unwrap()
-                                        });
-                                        ws_guard.send(Message::Text(response.to_string().into())).await.unwrap();
-                                        println!("Sent clientId to client: {}", client_id.as_ref().unwrap());
-                                    } else {
-                                        // If a socket with the same clientId already exists, close and remove the old connection
-                                        let mut connections = client_connections.lock().await;
-                                        let existing = connections.iter()
-                                            .find(|(_, conn)| conn.client_id.as_str() == client_id.as_ref().unwrap())
-                                            .map(|(k, _)| k.clone());
-                                        if let Some(existing_key) = existing {
-                                            connections.remove(&existing_key);
-                                        }
-                                    }
+                    // Reassemble chunked messages, then handle them as if they arrived in one frame
+                    let assembled;
+                    if let Some(chunk) = parsed.as_ref().ok().filter(|cmd| cmd.r#type == chunked_messages::CHUNK_TYPE) {
+                        match chunk_assembler.push(&chunk.payload) {
+                            Ok(Some(full)) => {
+                                assembled = full;
+                                raw = assembled.as_bytes();
+                                parsed = serde_json::from_str::<Command>(&assembled).map_err(ParseError::from);
+                            }
+                            Ok(None) => continue,
+                            Err(e) => {
+                                warn!("Connection {}: {}", current_connection_id, e);
+                                continue;
+                            }
+                        }
+                    }
+                    
+                    if let Ok(mut cmd) = parsed {
+                        message_id += 1;
+                        cmd.message_id = Some(message_id);
+                        cmd.connection_id = Some(current_connection_id);
 
-                                    let client_id = client_id.unwrap();
-                                    current_client_id = Some(client_id.clone());
-                                    cmd.client_id = Some(client_id.clone());
-
-                                    // Create connection object and add to connections
-                                    let mut connections = client_connections.lock().await;
-                                    let connection = ClientConnection {
-                                        id: current_connection_id,
-                                        address: format_address(&addr),
-                                        client_id: client_id.clone(),
-                                        socket: ws.clone(),
-                                    };
-                                    connections.insert(client_id.clone(), connection.clone());
-
-                                    // Emit connectionEstablished event
-                                    app_handle.emit("connectionEstablished", &serde_json::json!({
-                                        "id": current_connection_id,
-                                        "address": format_address(&addr),
-                                        "clientId": client_id,
-                                        "payload": cmd.payload,
-                                    })).unwrap();
-
-                                    println!("=== Sending current subscriptions ===");
-                                    let subs = subscriptions.lock().await;
-                                    println!("Current subscriptions: {:?}", *subs);
+                        debug!("Received {} from connection {}", cmd.r#type, current_connection_id);
+                        server_metrics::record_incoming(current_connection_id, &cmd.r#type, raw.len());
+
+                        // Commands stay as the client sent them until they leave towards the UI,
+                        // as time travel, mirrors and scripts send them back to apps. Redaction
+                        // rules are applied in `emit_command` and wherever else the UI gets data.
+
+                        // Handle client.intro
+                        if cmd.r#type == "client.intro" {
+                            debug!("Processing client.intro from connection {}", current_connection_id);
+                            encoding = WireEncoding::from_intro(&cmd.payload).unwrap_or(message_encoding);
+
+                            // Find partialConnection
+                            let mut partials = partial_connections.lock().await;
+                            let part_conn_opt = partials.iter().find(|c| c.id == current_connection_id).cloned();
+
+                            // Add address to payload
+                            if let Some(part_conn) = &part_conn_opt {
+                                if let Some(payload) = cmd.payload.as_object_mut() {
+                                    payload.insert("address".to_string(), serde_json::Value::String(part_conn.address.clone()));
                                 }
+                            }
 
-                                // Set client_id for all messages if current_client_id exists
-                                if let Some(client_id) = &current_client_id {
-                                    cmd.client_id = Some(client_id.clone());
+                            // Remove from partialConnections
+                            partials.retain(|c| c.id != current_connection_id);
+
+                            // Handle clientId
+                            let mut client_id = cmd.payload.get("clientId").and_then(|v| v.as_str()).map(|s| s.to_string());
+                            if client_id.is_none() || client_id.as_ref().map_or(false, |id| id == "~~~ null ~~~") {
+                                debug!("No clientId found, generating new one");
+                                client_id = Some(Uuid::new_v4().to_string());
+                                // Send clientId to client
+                                let response = serde_json::json!({
+                                    "type": "setClientId",
+                                    "payload": client_id.as_ref().unwrap()
+                                });
+                                if let Err(e) = socket.send_encoded("setClientId", &response, encoding) {
+                                    warn!("Error sending clientId to connection {}: {}", current_connection_id, e);
+                                }
+                                debug!("Sent clientId to client: {}", client_id.as_ref().unwrap());
+                            } else {
+                                // If a socket with the same clientId already exists, close and remove the old connection
+                                let mut connections = client_connections.lock().await;
+                                let existing = connections.iter()
+                                    .find(|(_, conn)| conn.client_id.as_str() == client_id.as_ref().unwrap())
+                                    .map(|(k, _)| k.clone());
+                                if let Some(existing_key) = existing {
+                                    connections.remove(&existing_key);
                                 }
+                            }
+
+                            let client_id = client_id.unwrap();
+                            current_client_id = Some(client_id.clone());
+                            cmd.client_id = Some(client_id.clone());
+
+                            // Create connection object and add to connections
+                            let mut connections = client_connections.lock().await;
+                            let connection = ClientConnection {
+                                id: current_connection_id,
+                                address: format_address(&addr),
+                                client_id: client_id.clone(),
+                                name: cmd.payload.get("name").and_then(|v| v.as_str()).map(|s| s.to_string()),
+                                encoding,
+                                socket: socket.clone(),
+                            };
+                            connections.insert(client_id.clone(), connection.clone());
+
+                            // Emit connectionEstablished event
+                            app_handle.emit("connectionEstablished", &serde_json::json!({
+                                "id": current_connection_id,
+                                "address": format_address(&addr),
+                                "clientId": client_id,
+                                "payload": redaction::redact_copy(&cmd.r#type, &cmd.payload),
+                            })).unwrap();
+
+                            server_metrics::client_identified(
+                                current_connection_id,
+                                &client_id,
+                                cmd.payload.get("name").and_then(|v| v.as_str()),
+                            );
+                            info!("Client {} connected from {} ({:?})", client_id, format_address(&addr), encoding);
+                        }
 
-                                // Handle state.values.subscribe
-                                if cmd.r#type == "state.values.subscribe" {
-                                    println!("=== Processing state.values.subscribe ===");
-                                    println!("Subscribe paths: {:?}", cmd.payload);
-                                    
-                                    // Add paths sent by client to subscription list
-                                    if let Some(paths) = cmd.payload.get("paths") {
-                                        if let Some(paths_array) = paths.as_array() {
-                                            let mut subs = subscriptions.lock().await;
-                                            for path in paths_array {
-                                                if let Some(path_str) = path.as_str() {
-                                                    if !subs.contains(&path_str.to_string()) {
-                                                        subs.push(path_str.to_string());
-                                                    }
-                                                }
+                        // Set client_id for all messages if current_client_id exists
+                        if let Some(client_id) = &current_client_id {
+                            cmd.client_id = Some(client_id.clone());
+                        }
+
+                        time_travel::observe(&app_handle, &cmd);
+                        custom_commands::observe(&cmd);
+                        benchmarks::observe(&cmd);
+                        api_analytics::observe(&cmd);
+
+                        // Mirror and record every completed action, throttled or not
+                        if cmd.r#type == "state.action.complete" {
+                            if let Some(client_id) = &cmd.client_id {
+                                mirror_mode::forward(&app_handle, client_id, &cmd.payload).await;
+                                action_scripts::record(client_id, &cmd.payload);
+                            }
+                        }
+
+                        // Answers to `client_requests::request` go to the caller, not the timeline
+                        let resolution = client_requests::resolve(&cmd);
+                        if resolution == client_requests::Resolution::Consumed {
+                            continue;
+                        }
+
+                        // Throttle floods before they reach the timeline. Every observer above,
+                        // mirrors and action scripts included, still sees every command.
+                        if let Some(limiter) = rate_limiter.as_mut() {
+                            match limiter.admit(cmd) {
+                                Some(admitted) => cmd = admitted,
+                                None => continue,
+                            }
+                        }
+
+                        if payload_validation::is_enabled() {
+                            cmd.validation_warnings = payload_validation::validate(&cmd.r#type, &cmd.payload);
+                            if let Some(warnings) = &cmd.validation_warnings {
+                                warn!(
+                                    "Invalid {} payload from connection {}: {}",
+                                    cmd.r#type, current_connection_id, warnings[0].message
+                                );
+                            }
+                        }
+
+                        // Handle state.values.subscribe
+                        if cmd.r#type == "state.values.subscribe" {
+                            debug!("Subscribe paths: {:?}", cmd.payload);
+                            
+                            // Add paths sent by client to subscription list
+                            if let Some(paths) = cmd.payload.get("paths") {
+                                if let Some(paths_array) = paths.as_array() {
+                                    let mut subs = subscriptions.lock().await;
+                                    for path in paths_array {
+                                        if let Some(path_str) = path.as_str() {
+                                            if !subs.contains(&path_str.to_string()) {
+                                                subs.push(path_str.to_string());
                                             }
                                         }
                                     }
-                                   
-                                    // Send subscription info to all clients
-                                    let command = CommandWithClientId {
-                                        r#type: "state.values.subscribe".to_string(),
-                                        payload: serde_json::json!({ "paths": *subscriptions.lock().await }),
-                                        client_id: cmd.client_id.clone().unwrap(),
-                                        important: false,
-                                        date: Some(chrono::Utc::now().to_rfc3339()),
-                                        delta_time: Some(0),
-                                    };
-                                    send_command(app_handle.clone(), command).await;
                                 }
+                            }
+                           
+                            // Send subscription info to all clients
+                            let command = CommandWithClientId {
+                                r#type: "state.values.subscribe".to_string(),
+                                payload: serde_json::json!({ "paths": *subscriptions.lock().await }),
+                                client_id: cmd.client_id.clone().unwrap(),
+                                important: false,
+                                date: Some(chrono::Utc::now().to_rfc3339()),
+                                delta_time: Some(0),
+                            };
+                            send_command(app_handle.clone(), command).await;
+                        }
 
-                                // Handle state.values.change
-                                if cmd.r#type == "state.values.change" {
-                                    println!("=== Processing state.values.change ===");
-                                    println!("Changes: {:?}", cmd.payload);
-                                    if let Some(changes) = cmd.payload.get("changes") {
-                                        if let Some(paths) = changes.as_array() {
-                                            let mut subs = subscriptions.lock().await;
-                                            subs.clear(); // Clear existing subscription list
-                                            for path in paths {
-                                                if let Some(path_str) = path.get("path").and_then(|p| p.as_str()) {
-                                                    subs.push(path_str.to_string());
-                                                }
-                                            }
-                                            println!("Current subscriptions: {:?}", *subs);
+                        // Handle state.values.change
+                        if cmd.r#type == "state.values.change" {
+                            if let Some(client_id) = &cmd.client_id {
+                                subscription_cache::record_changes(&app_handle, client_id, &cmd.payload);
+                            }
+                            if let Some(changes) = cmd.payload.get("changes") {
+                                if let Some(paths) = changes.as_array() {
+                                    let mut subs = subscriptions.lock().await;
+                                    subs.clear(); // Clear existing subscription list
+                                    for path in paths {
+                                        if let Some(path_str) = path.get("path").and_then(|p| p.as_str()) {
+                                            subs.push(path_str.to_string());
                                         }
                                     }
+                                    trace!("Current subscriptions: {:?}", *subs);
                                 }
+                            }
+                        }
 
-                                // Handle state.backup.response
-                                if cmd.r#type == "state.backup.response" {
-                                    if let Some(payload) = cmd.payload.as_object_mut() {
-                                        payload.insert("name".to_string(), serde_json::Value::Null);
-                                    }
+                        // Handle state.backup.response, keeping a copy in the snapshot library
+                        // when the user asked for the backup from the timeline
+                        if cmd.r#type == "state.backup.response" {
+                            let snapshot = match cmd.payload.get("state") {
+                                Some(state) if resolution == client_requests::Resolution::Timeline => {
+                                    let app_name = match &current_client_id {
+                                        Some(client_id) => get_client_name(client_id).await,
+                                        None => None,
+                                    };
+                                    let client_id = current_client_id.clone();
+                                    snapshot_library::save(app_handle.clone(), None, client_id, app_name, state.clone())
+                                        .await
+                                        .map_err(|e| warn!("Failed to store snapshot: {}", e))
+                                        .ok()
+                                }
+                                _ => None,
+                            };
+                            if let Some(payload) = cmd.payload.as_object_mut() {
+                                payload.insert("name".to_string(), serde_json::Value::Null);
+                                if let Some(snapshot) = snapshot {
+                                    payload.insert("snapshotId".to_string(), serde_json::Value::String(snapshot.id));
                                 }
-
-                                println!("=== Emitting command ===");
-                                println!("Command type: {}", cmd.r#type);
-                                println!("Command payload: {:?}", cmd.payload);
-                                app_handle.emit("command", &cmd).unwrap();
-                            } else {
-                                println!("Failed to parse command: {}", text);
                             }
                         }
-                        println!("=== Sending subscriptions ===");
-                        let subs = subscriptions.lock().await;
-                        println!("Sending subscriptions: {:?}", *subs);
-                    } else {
-                        break;
+
+                        emit_command(&app_handle, cmd, options.payload_offload_threshold);
+                    } else if let Err(e) = parsed {
+                        warn!("Failed to parse {:?} command from connection {}: {}", message_encoding, current_connection_id, e.message);
+                        server_metrics::record_parse_failure(current_connection_id, raw.len());
+
+                        let parse_error = CommandParseError::new(
+                            current_connection_id,
+                            current_client_id.clone(),
+                            format_address(&addr),
+                            message_encoding,
+                            raw,
+                            e,
+                        );
+                        parse_errors::report(&app_handle, &parse_error);
+                        if options.send_parse_errors_to_client {
+                            let _ = socket.send_encoded("commandParseError", &parse_error.to_client_message(), encoding);
+                        }
+                    }
+                }
+
+                // Report anything still throttled
+                if let Some(summary) = rate_limiter
+                    .as_mut()
+                    .and_then(|limiter| limiter.take_summary(current_connection_id, current_client_id.clone()))
+                {
+                    for cmd in rate_limiter::report(&app_handle, summary) {
+                        emit_command(&app_handle, cmd, options.payload_offload_threshold);
                     }
                 }
 
@@ -463,11 +971,18 @@ pub fn start_server(app_handle: AppHandle) {
                     let mut partials = partial_connections.lock().await;
                     partials.retain(|conn| conn.id != current_connection_id);
                 }
+                server_metrics::connection_closed(current_connection_id);
 
                 // Handle disconnection
                 if let Some(client_id) = current_client_id {
                     let mut connections = client_connections.lock().await;
+                    client_requests::cancel_client(&client_id);
+                    time_travel::remove_client(&client_id);
+                    mirror_mode::remove_client(&client_id);
+                    custom_commands::remove_client(&client_id);
+                    subscription_cache::remove_client(&client_id);
                     if let Some(conn) = connections.remove(&client_id) {
+                        info!("Client {} disconnected", client_id);
                         app_handle.emit("disconnect", &conn).unwrap();
                     }
                 }
@@ -480,8 +995,33 @@ pub fn start_server(app_handle: AppHandle) {
     *guard = Some(handle);
 }
 
+// Send a received command to the webview, redacted, keeping large payloads server-side
+fn emit_command(app_handle: &AppHandle, mut cmd: Command, payload_offload_threshold: usize) {
+    redaction::redact_command(&mut cmd);
+    push_command(app_handle, cmd, payload_offload_threshold);
+}
+
+fn push_command(app_handle: &AppHandle, mut cmd: Command, payload_offload_threshold: usize) {
+    trace!("Emitting command {}: {:?}", cmd.r#type, cmd.payload);
+    // Backups are kept whole, the snapshots page restores them from the command
+    if cmd.r#type != "state.backup.response" {
+        cmd.payload_handle = payload_store::offload(&mut cmd.payload, payload_offload_threshold);
+    }
+    command_batcher::push(app_handle, cmd);
+}
+
+// Show a command produced by the server itself on the timeline, returning
+// its payload as shown, after redaction
+pub async fn emit_server_command(app_handle: &AppHandle, mut cmd: Command) -> serde_json::Value {
+    redaction::redact_command(&mut cmd);
+    let payload = cmd.payload.clone();
+    let payload_offload_threshold = get_server_state().lock().await.options.payload_offload_threshold;
+    push_command(app_handle, cmd, payload_offload_threshold);
+    payload
+}
+
 pub async fn stop_server(app_handle: AppHandle) {
-    println!("Stopping server");
+    info!("Stopping server");
     let server_handle = get_server_handle();
     let server_state = get_server_state();
     
@@ -494,6 +1034,18 @@ pub async fn stop_server(app_handle: AppHandle) {
                 keep_alive_handle.abort();
             }
         }
+        if let Some(metrics_handle) = state.metrics_handle.lock().await.take() {
+            metrics_handle.abort();
+        }
+        if let Some(prometheus_handle) = state.prometheus_handle.lock().await.take() {
+            prometheus_handle.abort();
+        }
+        if let Some(batch_handle) = state.batch_handle.lock().await.take() {
+            batch_handle.abort();
+        }
+        if let Some(snapshotter_handle) = state.snapshotter_handle.lock().await.take() {
+            snapshotter_handle.abort();
+        }
         state.started = false;
     }
     
@@ -513,7 +1065,7 @@ pub async fn stop_server(app_handle: AppHandle) {
             let state = server_state.lock().await;
             state.options.port
         };
-        println!("WebSocket server stopped: ws://0.0.0.0:{}", port);
+        info!("WebSocket server stopped: ws://0.0.0.0:{}", port);
         
         // Clean up client connections
         let client_connections = get_client_connections();
@@ -529,6 +1081,13 @@ pub async fn stop_server(app_handle: AppHandle) {
         let subscriptions = get_subscriptions();
         let mut subs = subscriptions.lock().await;
         subs.clear();
+
+        server_metrics::clear();
+        subscription_cache::clear();
+        time_travel::clear();
+        custom_commands::clear();
+        api_analytics::clear();
+        command_batcher::flush(&app_handle);
         
         app_handle.emit("stop", "stop").unwrap();
     }
@@ -547,15 +1106,25 @@ pub async fn send_command(app_handle: AppHandle, command: CommandWithClientId) {
                 // Remove important, date, deltaTime fields
             });
             
-            let message = Message::Text(command_json.to_string().into());
-            let mut socket = conn.socket.lock().await;
-            if let Err(e) = socket.send(message).await {
-                println!("Error sending message to client {}: {}", conn.client_id, e);
+            if let Err(e) = conn.socket.send_encoded(&command.r#type, &command_json, conn.encoding) {
+                warn!("Error sending message to client {}: {}", conn.client_id, e);
             }
         }
     }
 }
 
+pub async fn is_client_connected(client_id: &str) -> bool {
+    get_client_connections().lock().await.contains_key(client_id)
+}
+
+pub async fn get_client_name(client_id: &str) -> Option<String> {
+    get_client_connections()
+        .lock()
+        .await
+        .get(client_id)
+        .and_then(|conn| conn.name.clone())
+}
+
 pub async fn send_custom_message(app_handle: AppHandle, value: String, client_id: Option<String>) {
     let command = CommandWithClientId {
         r#type: "custom".to_string(),
@@ -634,9 +1203,9 @@ fn start_keep_alive(app_handle: AppHandle) -> tauri::async_runtime::JoinHandle<(
             let connections = client_connections.lock().await;
             
             for (_, conn) in connections.iter() {
-                let mut socket = conn.socket.lock().await;
-                if let Err(e) = socket.send(Message::Ping(vec![].into())).await {
-                    println!("Error sending ping to client {}: {}", conn.client_id, e);
+                server_metrics::record_ping_sent(conn.id);
+                if let Err(e) = conn.socket.send(Message::Ping(vec![].into())) {
+                    warn!("Error sending ping to client {}: {}", conn.client_id, e);
                 }
             }
         }
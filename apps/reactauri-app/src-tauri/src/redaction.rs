@@ -0,0 +1,458 @@
+use crate::payload_store;
+use crate::reactauri_core_server::Command;
+use regex::{NoExpand, Regex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+const SETTINGS_STORE: &str = "redaction.json";
+const SETTINGS_KEY: &str = "settings";
+
+// What a rule replaces
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum RuleTarget {
+    // Values of a header, in any `headers` object of a payload
+    Header { name: String },
+    // Dot path from the payload root, `*` matching any key or index,
+    // e.g. `request.data.password` or `changes.*.value.token`
+    JsonPath { path: String },
+    // Matches inside any string value
+    Regex { pattern: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RedactionRule {
+    pub id: String,
+    #[serde(flatten)]
+    pub target: RuleTarget,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    // Command types the rule applies to, every type when empty
+    #[serde(default)]
+    pub command_types: Vec<String>,
+    #[serde(default = "default_replacement")]
+    pub replacement: String,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_replacement() -> String {
+    "[REDACTED]".to_string()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RedactionSettings {
+    pub rules: Vec<RedactionRule>,
+    // Keep the unredacted payload in memory, fetched with `get_payload`,
+    // instead of dropping it
+    #[serde(default)]
+    pub keep_raw_in_memory: bool,
+}
+
+// Set on commands a rule matched
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandRedaction {
+    pub rule_ids: Vec<String>,
+    pub raw_payload_handle: Option<String>,
+}
+
+enum Matcher {
+    // Lowercased header name
+    Header(String),
+    JsonPath(Vec<String>),
+    Regex(Regex),
+}
+
+// Parsed form of an enabled rule
+struct ActiveRule {
+    id: String,
+    matcher: Matcher,
+    command_types: Vec<String>,
+    replacement: String,
+}
+
+impl ActiveRule {
+    fn parse(rule: &RedactionRule) -> Result<Self, String> {
+        let matcher = match &rule.target {
+            RuleTarget::Header { name } => Matcher::Header(name.to_lowercase()),
+            RuleTarget::JsonPath { path } => {
+                let path = path.strip_prefix("$.").unwrap_or(path);
+                if path.is_empty() {
+                    return Err(format!("Rule {} has an empty path", rule.id));
+                }
+                Matcher::JsonPath(path.split('.').map(str::to_string).collect())
+            }
+            RuleTarget::Regex { pattern } => Matcher::Regex(
+                Regex::new(pattern).map_err(|e| format!("Invalid pattern in rule {}: {}", rule.id, e))?,
+            ),
+        };
+        Ok(Self {
+            id: rule.id.clone(),
+            matcher,
+            command_types: rule.command_types.clone(),
+            replacement: rule.replacement.clone(),
+        })
+    }
+
+    // Redact what the rule matches in `value`, returning the number of hits
+    fn apply(&self, command_type: &str, value: &mut Value) -> u64 {
+        if !self.command_types.is_empty() && !self.command_types.iter().any(|t| t == command_type) {
+            return 0;
+        }
+        match &self.matcher {
+            Matcher::Header(name) => redact_headers(value, name, &self.replacement),
+            Matcher::JsonPath(path) => redact_path(value, path, &self.replacement),
+            Matcher::Regex(regex) => redact_strings(value, regex, &self.replacement),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Redaction {
+    settings: RedactionSettings,
+    rules: Vec<ActiveRule>,
+    // Values replaced by each rule, by rule id
+    hits: HashMap<String, u64>,
+}
+
+static REDACTION: OnceLock<Mutex<Redaction>> = OnceLock::new();
+
+fn get_redaction() -> &'static Mutex<Redaction> {
+    REDACTION.get_or_init(|| Mutex::new(Redaction::default()))
+}
+
+// Values already replaced are left alone, so redacting twice counts once
+fn replace(value: &mut Value, replacement: &str) -> u64 {
+    if value.as_str() == Some(replacement) {
+        return 0;
+    }
+    *value = Value::String(replacement.to_string());
+    1
+}
+
+fn redact_headers(value: &mut Value, name: &str, replacement: &str) -> u64 {
+    match value {
+        Value::Object(object) => object
+            .iter_mut()
+            .map(|(key, child)| {
+                let mut hits = 0;
+                if key.eq_ignore_ascii_case("headers") {
+                    if let Some(headers) = child.as_object_mut() {
+                        for (header, header_value) in headers.iter_mut() {
+                            if header.to_lowercase() == name {
+                                hits += replace(header_value, replacement);
+                            }
+                        }
+                    }
+                }
+                hits + redact_headers(child, name, replacement)
+            })
+            .sum(),
+        Value::Array(values) => values.iter_mut().map(|v| redact_headers(v, name, replacement)).sum(),
+        _ => 0,
+    }
+}
+
+fn redact_path(value: &mut Value, path: &[String], replacement: &str) -> u64 {
+    let Some((segment, rest)) = path.split_first() else {
+        return replace(value, replacement);
+    };
+    match value {
+        Value::Object(object) if segment == "*" => object.values_mut().map(|v| redact_path(v, rest, replacement)).sum(),
+        Value::Object(object) => object
+            .get_mut(segment)
+            .map(|v| redact_path(v, rest, replacement))
+            .unwrap_or(0),
+        Value::Array(values) if segment == "*" => values.iter_mut().map(|v| redact_path(v, rest, replacement)).sum(),
+        Value::Array(values) => segment
+            .parse::<usize>()
+            .ok()
+            .and_then(|index| values.get_mut(index))
+            .map(|v| redact_path(v, rest, replacement))
+            .unwrap_or(0),
+        _ => 0,
+    }
+}
+
+fn redact_strings(value: &mut Value, regex: &Regex, replacement: &str) -> u64 {
+    match value {
+        Value::String(text) => {
+            let hits = regex.find_iter(text).filter(|m| m.as_str() != replacement).count() as u64;
+            if hits > 0 {
+                *text = regex.replace_all(text, NoExpand(replacement)).into_owned();
+            }
+            hits
+        }
+        Value::Object(object) => object.values_mut().map(|v| redact_strings(v, regex, replacement)).sum(),
+        Value::Array(values) => values.iter_mut().map(|v| redact_strings(v, regex, replacement)).sum(),
+        _ => 0,
+    }
+}
+
+fn apply_settings(settings: RedactionSettings) -> Result<RedactionSettings, String> {
+    let rules = settings
+        .rules
+        .iter()
+        .filter(|rule| rule.enabled)
+        .map(ActiveRule::parse)
+        .collect::<Result<Vec<_>, String>>()?;
+    let mut redaction = get_redaction().lock().unwrap();
+    redaction.settings = settings.clone();
+    redaction.rules = rules;
+    Ok(settings)
+}
+
+// Rules for tests elsewhere that need redaction to be on: `secret-<digits>`
+// is replaced in every command type
+#[cfg(test)]
+pub fn use_test_rules() {
+    apply_settings(RedactionSettings {
+        rules: vec![RedactionRule {
+            id: "test-secret".to_string(),
+            target: RuleTarget::Regex {
+                pattern: "secret-[0-9]+".to_string(),
+            },
+            enabled: true,
+            command_types: Vec::new(),
+            replacement: default_replacement(),
+        }],
+        keep_raw_in_memory: false,
+    })
+    .unwrap();
+}
+
+// Restore the rules saved from a previous session
+pub fn init(app: &AppHandle) {
+    let saved = app
+        .store(SETTINGS_STORE)
+        .ok()
+        .and_then(|store| store.get(SETTINGS_KEY))
+        .and_then(|value| serde_json::from_value::<RedactionSettings>(value).ok())
+        .unwrap_or_default();
+    if let Err(e) = apply_settings(saved) {
+        log::warn!("Ignoring saved redaction rules: {}", e);
+    }
+}
+
+pub fn is_active() -> bool {
+    !get_redaction().lock().unwrap().rules.is_empty()
+}
+
+// Apply every rule to a payload of the given command type, returning the ids
+// of the rules that matched
+pub fn redact_value(command_type: &str, value: &mut Value) -> Vec<String> {
+    let mut redaction = get_redaction().lock().unwrap();
+    let Redaction { rules, hits, .. } = &mut *redaction;
+    let mut matched = Vec::new();
+    for rule in rules.iter() {
+        let count = rule.apply(command_type, value);
+        if count > 0 {
+            *hits.entry(rule.id.clone()).or_default() += count;
+            matched.push(rule.id.clone());
+        }
+    }
+    matched
+}
+
+// A redacted copy of a value, for what the UI, files and exports get outside
+// of the timeline. Only timeline commands count towards the rule hits.
+pub fn redact_copy(command_type: &str, value: &Value) -> Value {
+    let mut copy = value.clone();
+    let redaction = get_redaction().lock().unwrap();
+    for rule in &redaction.rules {
+        rule.apply(command_type, &mut copy);
+    }
+    copy
+}
+
+// Redact text that could not be parsed, like the excerpt of a malformed
+// message. Its command type is unknown so every regex rule applies. Header
+// and path rules cannot be matched in raw text, None is returned while any
+// is on.
+pub fn redact_text(text: &str) -> Option<String> {
+    let redaction = get_redaction().lock().unwrap();
+    let mut text = Value::String(text.to_string());
+    for rule in &redaction.rules {
+        match &rule.matcher {
+            Matcher::Regex(regex) => {
+                redact_strings(&mut text, regex, &rule.replacement);
+            }
+            _ => return None,
+        }
+    }
+    match text {
+        Value::String(text) => Some(text),
+        _ => None,
+    }
+}
+
+// Redact a command before it is emitted to the timeline
+pub fn redact_command(cmd: &mut Command) {
+    let keep_raw = {
+        let redaction = get_redaction().lock().unwrap();
+        if redaction.rules.is_empty() {
+            return;
+        }
+        redaction.settings.keep_raw_in_memory
+    };
+
+    let raw = keep_raw.then(|| cmd.payload.clone());
+    let rule_ids = redact_value(&cmd.r#type, &mut cmd.payload);
+    if rule_ids.is_empty() {
+        return;
+    }
+    let raw_payload_handle = raw.map(payload_store::keep);
+    match &mut cmd.redaction {
+        Some(redaction) => {
+            redaction.rule_ids.extend(rule_ids);
+            if raw_payload_handle.is_some() {
+                redaction.raw_payload_handle = raw_payload_handle;
+            }
+        }
+        None => {
+            cmd.redaction = Some(CommandRedaction {
+                rule_ids,
+                raw_payload_handle,
+            })
+        }
+    }
+}
+
+// Redact a state, as found in `state.backup.response`
+pub fn redact_state(state: &Value) -> Value {
+    let payload = serde_json::json!({ "state": state });
+    redact_copy("state.backup.response", &payload)["state"].take()
+}
+
+#[tauri::command]
+pub fn get_redaction_settings() -> RedactionSettings {
+    get_redaction().lock().unwrap().settings.clone()
+}
+
+#[tauri::command]
+pub fn set_redaction_settings(app: AppHandle, settings: RedactionSettings) -> Result<RedactionSettings, String> {
+    let settings = apply_settings(settings)?;
+
+    let store = app
+        .store(SETTINGS_STORE)
+        .map_err(|e| format!("Failed to open redaction settings: {}", e))?;
+    store.set(SETTINGS_KEY, serde_json::to_value(&settings).map_err(|e| e.to_string())?);
+    store
+        .save()
+        .map_err(|e| format!("Failed to save redaction settings: {}", e))?;
+    Ok(settings)
+}
+
+// Values replaced by each rule since startup or the last reset
+#[tauri::command]
+pub fn get_redaction_hits() -> HashMap<String, u64> {
+    get_redaction().lock().unwrap().hits.clone()
+}
+
+#[tauri::command]
+pub fn reset_redaction_hits() {
+    get_redaction().lock().unwrap().hits.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn rule(target: RuleTarget, command_types: &[&str]) -> ActiveRule {
+        ActiveRule::parse(&RedactionRule {
+            id: "rule".to_string(),
+            target,
+            enabled: true,
+            command_types: command_types.iter().map(|t| t.to_string()).collect(),
+            replacement: default_replacement(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn header_rules_match_any_headers_object_ignoring_case() {
+        let header = rule(RuleTarget::Header { name: "Authorization".to_string() }, &[]);
+        let mut payload = json!({
+            "request": { "headers": { "authorization": "Bearer abc", "Accept": "*/*" } },
+            "response": { "Headers": { "AUTHORIZATION": "Bearer def" } },
+        });
+        assert_eq!(header.apply("api.response", &mut payload), 2);
+        assert_eq!(payload["request"]["headers"]["authorization"], "[REDACTED]");
+        assert_eq!(payload["request"]["headers"]["Accept"], "*/*");
+        assert_eq!(payload["response"]["Headers"]["AUTHORIZATION"], "[REDACTED]");
+    }
+
+    #[test]
+    fn path_rules_follow_keys_indexes_and_wildcards() {
+        let wildcard = rule(RuleTarget::JsonPath { path: "$.changes.*.value.token".to_string() }, &[]);
+        let mut payload = json!({ "changes": [{ "value": { "token": "a" } }, { "value": { "other": "b" } }] });
+        assert_eq!(wildcard.apply("state.values.change", &mut payload), 1);
+        assert_eq!(payload, json!({ "changes": [{ "value": { "token": "[REDACTED]" } }, { "value": { "other": "b" } }] }));
+
+        let indexed = rule(RuleTarget::JsonPath { path: "users.1.password".to_string() }, &[]);
+        let mut payload = json!({ "users": [{ "password": "a" }, { "password": "b" }] });
+        assert_eq!(indexed.apply("log", &mut payload), 1);
+        assert_eq!(payload["users"][0]["password"], "a");
+        assert_eq!(payload["users"][1]["password"], "[REDACTED]");
+    }
+
+    #[test]
+    fn regex_rules_replace_inside_strings_literally() {
+        let cards = ActiveRule::parse(&RedactionRule {
+            id: "cards".to_string(),
+            target: RuleTarget::Regex { pattern: r"\d{4}-\d{4}".to_string() },
+            enabled: true,
+            command_types: Vec::new(),
+            replacement: "$0-hidden".to_string(),
+        })
+        .unwrap();
+        let mut payload = json!({ "message": "card 1234-5678 and 8765-4321", "nested": [{ "n": 12345678 }] });
+        assert_eq!(cards.apply("log", &mut payload), 2);
+        assert_eq!(payload["message"], "card $0-hidden and $0-hidden");
+        assert_eq!(payload["nested"][0]["n"], 12345678);
+    }
+
+    #[test]
+    fn rules_only_apply_to_their_command_types() {
+        let logs_only = rule(RuleTarget::JsonPath { path: "secret".to_string() }, &["log"]);
+        let mut payload = json!({ "secret": "s" });
+        assert_eq!(logs_only.apply("display", &mut payload), 0);
+        assert_eq!(logs_only.apply("log", &mut payload), 1);
+    }
+
+    #[test]
+    fn redacting_twice_counts_once() {
+        let regex = rule(RuleTarget::Regex { pattern: "secret".to_string() }, &[]);
+        let mut payload = json!({ "a": "secret", "b": "my secret" });
+        assert_eq!(regex.apply("log", &mut payload), 2);
+        assert_eq!(regex.apply("log", &mut payload), 0);
+
+        let path = rule(RuleTarget::JsonPath { path: "a".to_string() }, &[]);
+        assert_eq!(path.apply("log", &mut payload), 0);
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        let parse = |target| {
+            ActiveRule::parse(&RedactionRule {
+                id: "bad".to_string(),
+                target,
+                enabled: true,
+                command_types: Vec::new(),
+                replacement: default_replacement(),
+            })
+        };
+        assert!(parse(RuleTarget::JsonPath { path: "$.".to_string() }).is_err());
+        assert!(parse(RuleTarget::Regex { pattern: "(".to_string() }).is_err());
+    }
+}
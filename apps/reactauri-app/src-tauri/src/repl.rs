@@ -0,0 +1,105 @@
+use crate::client_requests;
+use log::warn;
+use serde_json::Value;
+use std::time::Duration;
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+// Input history, one list per client id
+const HISTORY_STORE: &str = "repl-history.json";
+const MAX_HISTORY_ENTRIES: usize = 200;
+
+fn timeout_or_default(timeout_ms: Option<u64>) -> Duration {
+    timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or(client_requests::DEFAULT_TIMEOUT)
+}
+
+fn load_history(app: &AppHandle, client_id: &str) -> Result<Vec<String>, String> {
+    let store = app
+        .store(HISTORY_STORE)
+        .map_err(|e| format!("Failed to open REPL history: {}", e))?;
+    Ok(store
+        .get(client_id)
+        .and_then(|history| serde_json::from_value(history).ok())
+        .unwrap_or_default())
+}
+
+fn save_history(app: &AppHandle, client_id: &str, history: Vec<String>) -> Result<(), String> {
+    let store = app
+        .store(HISTORY_STORE)
+        .map_err(|e| format!("Failed to open REPL history: {}", e))?;
+    store.set(client_id, history);
+    store
+        .save()
+        .map_err(|e| format!("Failed to save REPL history: {}", e))
+}
+
+// Remember an input, most recent last, skipping repeats of the previous one
+fn push_history(app: &AppHandle, client_id: &str, code: &str) -> Result<(), String> {
+    let mut history = load_history(app, client_id)?;
+    if history.last().is_some_and(|last| last == code) {
+        return Ok(());
+    }
+    history.push(code.to_string());
+    if history.len() > MAX_HISTORY_ENTRIES {
+        history.drain(..history.len() - MAX_HISTORY_ENTRIES);
+    }
+    save_history(app, client_id, history)
+}
+
+// Names of the objects a client exposes to the REPL. The client's repl
+// plugin ignores the payload of `repl.ls`, so none is sent.
+#[tauri::command]
+pub async fn repl_ls(app: AppHandle, client_id: String, timeout_ms: Option<u64>) -> Result<Vec<String>, String> {
+    let payload = client_requests::request(
+        app,
+        &client_id,
+        "repl.ls",
+        Value::Null,
+        "repl.ls.response",
+        None,
+        timeout_or_default(timeout_ms),
+    )
+    .await?;
+    client_requests::redacted("repl.ls.response", payload)
+        .and_then(|payload| serde_json::from_value(payload).map_err(|e| format!("Invalid repl.ls.response: {}", e)))
+}
+
+// Evaluate `code` on a client. Clients answer in order, so the oldest
+// pending execution gets the next response.
+#[tauri::command]
+pub async fn repl_execute(
+    app: AppHandle,
+    client_id: String,
+    code: String,
+    timeout_ms: Option<u64>,
+) -> Result<Value, String> {
+    let result = client_requests::request(
+        app.clone(),
+        &client_id,
+        "repl.execute",
+        Value::String(code.clone()),
+        "repl.execute.response",
+        None,
+        timeout_or_default(timeout_ms),
+    )
+    .await
+    .and_then(|result| client_requests::redacted("repl.execute.response", result))?;
+    // Only inputs the client ran make it into the history
+    if let Err(e) = push_history(&app, &client_id, &code) {
+        warn!("{}", e);
+    }
+    Ok(result)
+}
+
+// Inputs sent to a client, oldest first
+#[tauri::command]
+pub fn get_repl_history(app: AppHandle, client_id: String) -> Result<Vec<String>, String> {
+    load_history(&app, &client_id)
+}
+
+#[tauri::command]
+pub fn clear_repl_history(app: AppHandle, client_id: String) -> Result<(), String> {
+    save_history(&app, &client_id, Vec::new())
+}
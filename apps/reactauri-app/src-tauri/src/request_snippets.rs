@@ -0,0 +1,355 @@
+use crate::payload_store;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::AppHandle;
+use tauri_plugin_clipboard_manager::ClipboardExt;
+
+// Headers the tools compute themselves
+const SKIPPED_HEADERS: [&str; 2] = ["content-length", "host"];
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SnippetFormat {
+    Curl,
+    Fetch,
+    Httpie,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestSnippets {
+    pub curl: String,
+    pub fetch: String,
+    pub httpie: String,
+}
+
+pub enum Body {
+    // Sent as is
+    Text(String),
+    // Serialized as JSON, with a JSON content type unless one is set
+    Json(Value),
+}
+
+// The request section of an `api.response` payload
+pub struct ApiRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Body>,
+}
+
+impl ApiRequest {
+    pub fn from_payload(payload: &Value) -> Result<Self, String> {
+        let request = payload
+            .get("request")
+            .ok_or("Not an api.response payload: no request")?;
+        let url = request
+            .get("url")
+            .and_then(|u| u.as_str())
+            .ok_or("The request has no URL")?;
+        let method = request
+            .get("method")
+            .and_then(|m| m.as_str())
+            .unwrap_or("GET")
+            .to_uppercase();
+
+        let mut headers = Vec::new();
+        if let Some(map) = request.get("headers").and_then(|h| h.as_object()) {
+            for (name, value) in map {
+                if SKIPPED_HEADERS.contains(&name.to_lowercase().as_str()) {
+                    continue;
+                }
+                let value = match value {
+                    Value::Null => continue,
+                    Value::String(s) => s.clone(),
+                    Value::Array(values) => values.iter().map(header_value).collect::<Vec<_>>().join(", "),
+                    other => header_value(other),
+                };
+                headers.push((name.clone(), value));
+            }
+        }
+
+        let body = match request.get("data") {
+            None | Some(Value::Null) => None,
+            Some(Value::String(text)) if text.is_empty() => None,
+            Some(Value::String(text)) => Some(Body::Text(text.clone())),
+            Some(value) => Some(Body::Json(value.clone())),
+        };
+        if matches!(body, Some(Body::Json(_))) && !headers.iter().any(|(name, _)| name.eq_ignore_ascii_case("content-type")) {
+            headers.push(("Content-Type".to_string(), "application/json".to_string()));
+        }
+
+        Ok(Self {
+            method,
+            url: with_params(url, request.get("params")),
+            headers,
+            body,
+        })
+    }
+
+    pub fn body_text(&self) -> Option<String> {
+        self.body.as_ref().map(|body| match body {
+            Body::Text(text) => text.clone(),
+            Body::Json(value) => value.to_string(),
+        })
+    }
+}
+
+fn header_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn percent_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+// Append the `params` object axios and apisauce send separately from the URL
+fn with_params(url: &str, params: Option<&Value>) -> String {
+    let Some(params) = params.and_then(|p| p.as_object()) else {
+        return url.to_string();
+    };
+    let mut pairs = Vec::new();
+    for (key, value) in params {
+        let values = match value {
+            Value::Null => continue,
+            Value::Array(values) => values.iter().filter(|v| !v.is_null()).map(header_value).collect(),
+            other => vec![header_value(other)],
+        };
+        for value in values {
+            pairs.push(format!("{}={}", percent_encode(key), percent_encode(&value)));
+        }
+    }
+    if pairs.is_empty() {
+        return url.to_string();
+    }
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}{}", url, separator, pairs.join("&"))
+}
+
+// POSIX shell quoting: everything inside single quotes is literal, a single
+// quote is closed, escaped and reopened
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+// JavaScript string literal, JSON strings being valid ones
+fn js_string(text: &str) -> String {
+    Value::String(text.to_string()).to_string()
+}
+
+fn to_curl(request: &ApiRequest) -> String {
+    let mut parts = vec![format!("curl {}", shell_quote(&request.url))];
+    if request.method != "GET" || request.body.is_some() {
+        parts.push(format!("-X {}", request.method));
+    }
+    for (name, value) in &request.headers {
+        parts.push(format!("-H {}", shell_quote(&format!("{}: {}", name, value))));
+    }
+    if let Some(body) = request.body_text() {
+        parts.push(format!("--data-raw {}", shell_quote(&body)));
+    }
+    parts.join(" \\\n  ")
+}
+
+fn to_fetch(request: &ApiRequest) -> String {
+    let mut options = vec![format!("  method: {},", js_string(&request.method))];
+    if !request.headers.is_empty() {
+        let headers: Vec<String> = request
+            .headers
+            .iter()
+            .map(|(name, value)| format!("    {}: {},", js_string(name), js_string(value)))
+            .collect();
+        options.push(format!("  headers: {{\n{}\n  }},", headers.join("\n")));
+    }
+    match &request.body {
+        Some(Body::Json(value)) => {
+            let json = serde_json::to_string_pretty(value).unwrap_or_default().replace('\n', "\n  ");
+            options.push(format!("  body: JSON.stringify({}),", json));
+        }
+        Some(Body::Text(text)) => options.push(format!("  body: {},", js_string(text))),
+        None => {}
+    }
+    format!("fetch({}, {{\n{}\n}});", js_string(&request.url), options.join("\n"))
+}
+
+fn to_httpie(request: &ApiRequest) -> String {
+    let mut parts = vec![format!("http {} {}", request.method, shell_quote(&request.url))];
+    for (name, value) in &request.headers {
+        parts.push(shell_quote(&format!("{}:{}", name, value)));
+    }
+    if let Some(body) = request.body_text() {
+        parts.push(format!("--raw {}", shell_quote(&body)));
+    }
+    parts.join(" \\\n  ")
+}
+
+fn render(request: &ApiRequest, format: SnippetFormat) -> String {
+    match format {
+        SnippetFormat::Curl => to_curl(request),
+        SnippetFormat::Fetch => to_fetch(request),
+        SnippetFormat::Httpie => to_httpie(request),
+    }
+}
+
+// The payload given, or the one offloaded under `payload_handle`
+pub fn resolve_payload(payload: Option<Value>, payload_handle: Option<String>) -> Result<Value, String> {
+    match (payload_handle, payload) {
+        (Some(handle), _) => payload_store::get_payload(handle, None),
+        (None, Some(payload)) => Ok(payload),
+        (None, None) => Err("No api.response payload given".to_string()),
+    }
+}
+
+// The unredacted payload redaction kept for `shown`, so requests are built
+// from the real values, or `shown` itself when there is none
+pub fn resolve_raw_payload(raw_payload_handle: Option<String>, shown: Value) -> Value {
+    raw_payload_handle
+        .and_then(|handle| payload_store::get_payload(handle, None).ok())
+        .unwrap_or(shown)
+}
+
+#[tauri::command]
+pub fn get_request_snippets(
+    payload: Option<Value>,
+    payload_handle: Option<String>,
+    raw_payload_handle: Option<String>,
+) -> Result<RequestSnippets, String> {
+    let payload = resolve_raw_payload(raw_payload_handle, resolve_payload(payload, payload_handle)?);
+    let request = ApiRequest::from_payload(&payload)?;
+    Ok(RequestSnippets {
+        curl: to_curl(&request),
+        fetch: to_fetch(&request),
+        httpie: to_httpie(&request),
+    })
+}
+
+// Put a snippet on the clipboard, returning it as well
+#[tauri::command]
+pub fn copy_request_snippet(
+    app: AppHandle,
+    format: SnippetFormat,
+    payload: Option<Value>,
+    payload_handle: Option<String>,
+    raw_payload_handle: Option<String>,
+) -> Result<String, String> {
+    let payload = resolve_raw_payload(raw_payload_handle, resolve_payload(payload, payload_handle)?);
+    let request = ApiRequest::from_payload(&payload)?;
+    let snippet = render(&request, format);
+    app.clipboard()
+        .write_text(snippet.clone())
+        .map_err(|e| format!("Failed to copy snippet: {}", e))?;
+    Ok(snippet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn post() -> ApiRequest {
+        ApiRequest::from_payload(&json!({
+            "request": {
+                "url": "https://api.test/users?sort=name",
+                "method": "post",
+                "headers": { "Authorization": "Bearer it's", "Content-Length": "27", "X-Ids": [1, 2] },
+                "params": { "q": "a b&c", "skip": null, "tag": ["x", "y"] },
+                "data": { "name": "O'Brien" },
+            },
+            "response": { "status": 201 },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn shell_quote_escapes_single_quotes() {
+        assert_eq!(shell_quote("plain"), "'plain'");
+        assert_eq!(shell_quote("it's $HOME"), r"'it'\''s $HOME'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn requests_are_read_from_api_response_payloads() {
+        let request = post();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.url, "https://api.test/users?sort=name&q=a%20b%26c&tag=x&tag=y");
+        assert_eq!(
+            request.headers,
+            [
+                ("Authorization".to_string(), "Bearer it's".to_string()),
+                ("X-Ids".to_string(), "1, 2".to_string()),
+                ("Content-Type".to_string(), "application/json".to_string()),
+            ]
+        );
+        assert_eq!(request.body_text().as_deref(), Some(r#"{"name":"O'Brien"}"#));
+        assert!(ApiRequest::from_payload(&json!({ "request": {} })).is_err());
+    }
+
+    #[test]
+    fn curl_snippet() {
+        assert_eq!(
+            to_curl(&post()),
+            [
+                "curl 'https://api.test/users?sort=name&q=a%20b%26c&tag=x&tag=y'",
+                r"-X POST",
+                r"-H 'Authorization: Bearer it'\''s'",
+                "-H 'X-Ids: 1, 2'",
+                "-H 'Content-Type: application/json'",
+                r#"--data-raw '{"name":"O'\''Brien"}'"#,
+            ]
+            .join(" \\\n  ")
+        );
+        let get = ApiRequest::from_payload(&json!({ "request": { "url": "https://api.test/" } })).unwrap();
+        assert_eq!(to_curl(&get), "curl 'https://api.test/'");
+    }
+
+    #[test]
+    fn fetch_snippet() {
+        assert_eq!(
+            to_fetch(&post()),
+            r#"fetch("https://api.test/users?sort=name&q=a%20b%26c&tag=x&tag=y", {
+  method: "POST",
+  headers: {
+    "Authorization": "Bearer it's",
+    "X-Ids": "1, 2",
+    "Content-Type": "application/json",
+  },
+  body: JSON.stringify({
+    "name": "O'Brien"
+  }),
+});"#
+        );
+        let text = ApiRequest::from_payload(&json!({
+            "request": { "url": "/echo", "method": "PUT", "data": "line\n\"quoted\"" },
+        }))
+        .unwrap();
+        assert_eq!(
+            to_fetch(&text),
+            "fetch(\"/echo\", {\n  method: \"PUT\",\n  body: \"line\\n\\\"quoted\\\"\",\n});"
+        );
+    }
+
+    #[test]
+    fn httpie_snippet() {
+        assert_eq!(
+            to_httpie(&post()),
+            [
+                "http POST 'https://api.test/users?sort=name&q=a%20b%26c&tag=x&tag=y'",
+                r"'Authorization:Bearer it'\''s'",
+                "'X-Ids:1, 2'",
+                "'Content-Type:application/json'",
+                r#"--raw '{"name":"O'\''Brien"}'"#,
+            ]
+            .join(" \\\n  ")
+        );
+    }
+}
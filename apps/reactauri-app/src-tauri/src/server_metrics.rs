@@ -0,0 +1,442 @@
+use log::{info, warn};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::async_runtime;
+use tauri::AppHandle;
+use tauri::Emitter;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::time::interval;
+
+type CounterField = fn(&CommandCounters) -> u64;
+type CompressionField = fn(&CompressionMetrics) -> u64;
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandCounters {
+    pub messages_in: u64,
+    pub bytes_in: u64,
+    pub messages_out: u64,
+    pub bytes_out: u64,
+}
+
+// permessage-deflate traffic; `wire` is the compressed size, `raw` the inflated size
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompressionMetrics {
+    pub negotiated: bool,
+    pub wire_bytes_in: u64,
+    pub raw_bytes_in: u64,
+    pub wire_bytes_out: u64,
+    pub raw_bytes_out: u64,
+    // Raw bytes per wire byte of the compressed messages, None until one was sent
+    pub ratio_in: Option<f64>,
+    pub ratio_out: Option<f64>,
+}
+
+fn ratio(raw: u64, wire: u64) -> Option<f64> {
+    (wire > 0).then(|| raw as f64 / wire as f64)
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionMetrics {
+    pub connection_id: u32,
+    pub address: String,
+    pub client_id: Option<String>,
+    pub name: Option<String>,
+    pub connected_at: String,
+    #[serde(flatten)]
+    pub totals: CommandCounters,
+    pub by_type: HashMap<String, CommandCounters>,
+    pub parse_failures: u64,
+    pub throttled: u64,
+    pub ping_rtt_ms: Option<f64>,
+    pub queue_depth: u64,
+    pub compression: CompressionMetrics,
+    #[serde(skip)]
+    ping_sent_at: Option<Instant>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerMetrics {
+    pub timestamp: String,
+    pub connections: Vec<ConnectionMetrics>,
+}
+
+static CONNECTION_METRICS: OnceLock<Mutex<HashMap<u32, ConnectionMetrics>>> = OnceLock::new();
+
+fn get_connection_metrics() -> &'static Mutex<HashMap<u32, ConnectionMetrics>> {
+    CONNECTION_METRICS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// Run `f` against the metrics of a connection, if it is still tracked
+fn with_connection<F: FnOnce(&mut ConnectionMetrics)>(connection_id: u32, f: F) {
+    let mut metrics = get_connection_metrics().lock().unwrap();
+    if let Some(connection) = metrics.get_mut(&connection_id) {
+        f(connection);
+    }
+}
+
+pub fn connection_opened(connection_id: u32, address: String) {
+    let mut metrics = get_connection_metrics().lock().unwrap();
+    metrics.insert(
+        connection_id,
+        ConnectionMetrics {
+            connection_id,
+            address,
+            client_id: None,
+            name: None,
+            connected_at: chrono::Utc::now().to_rfc3339(),
+            totals: CommandCounters::default(),
+            by_type: HashMap::new(),
+            parse_failures: 0,
+            throttled: 0,
+            ping_rtt_ms: None,
+            queue_depth: 0,
+            compression: CompressionMetrics::default(),
+            ping_sent_at: None,
+        },
+    );
+}
+
+pub fn connection_closed(connection_id: u32) {
+    get_connection_metrics().lock().unwrap().remove(&connection_id);
+}
+
+pub fn client_identified(connection_id: u32, client_id: &str, name: Option<&str>) {
+    with_connection(connection_id, |connection| {
+        connection.client_id = Some(client_id.to_string());
+        connection.name = name.map(|n| n.to_string());
+    });
+}
+
+pub fn record_incoming(connection_id: u32, command_type: &str, bytes: usize) {
+    with_connection(connection_id, |connection| {
+        let counters = connection.by_type.entry(command_type.to_string()).or_default();
+        counters.messages_in += 1;
+        counters.bytes_in += bytes as u64;
+        connection.totals.messages_in += 1;
+        connection.totals.bytes_in += bytes as u64;
+    });
+}
+
+pub fn record_outgoing(connection_id: u32, command_type: &str, bytes: usize) {
+    with_connection(connection_id, |connection| {
+        let counters = connection.by_type.entry(command_type.to_string()).or_default();
+        counters.messages_out += 1;
+        counters.bytes_out += bytes as u64;
+        connection.totals.messages_out += 1;
+        connection.totals.bytes_out += bytes as u64;
+    });
+}
+
+pub fn record_parse_failure(connection_id: u32, bytes: usize) {
+    with_connection(connection_id, |connection| {
+        connection.parse_failures += 1;
+        connection.totals.messages_in += 1;
+        connection.totals.bytes_in += bytes as u64;
+    });
+}
+
+pub fn record_throttled(connection_id: u32, count: u64) {
+    with_connection(connection_id, |connection| connection.throttled += count);
+}
+
+pub fn compression_negotiated(connection_id: u32) {
+    with_connection(connection_id, |connection| connection.compression.negotiated = true);
+}
+
+pub fn record_compressed_in(connection_id: u32, wire_bytes: usize, raw_bytes: usize) {
+    with_connection(connection_id, |connection| {
+        let compression = &mut connection.compression;
+        compression.wire_bytes_in += wire_bytes as u64;
+        compression.raw_bytes_in += raw_bytes as u64;
+        compression.ratio_in = ratio(compression.raw_bytes_in, compression.wire_bytes_in);
+    });
+}
+
+pub fn record_compressed_out(connection_id: u32, raw_bytes: usize, wire_bytes: usize) {
+    with_connection(connection_id, |connection| {
+        let compression = &mut connection.compression;
+        compression.wire_bytes_out += wire_bytes as u64;
+        compression.raw_bytes_out += raw_bytes as u64;
+        compression.ratio_out = ratio(compression.raw_bytes_out, compression.wire_bytes_out);
+    });
+}
+
+pub fn record_ping_sent(connection_id: u32) {
+    with_connection(connection_id, |connection| {
+        connection.ping_sent_at = Some(Instant::now());
+    });
+}
+
+pub fn record_pong(connection_id: u32) {
+    with_connection(connection_id, |connection| {
+        if let Some(sent_at) = connection.ping_sent_at.take() {
+            connection.ping_rtt_ms = Some(sent_at.elapsed().as_secs_f64() * 1000.0);
+        }
+    });
+}
+
+pub fn message_queued(connection_id: u32) {
+    with_connection(connection_id, |connection| connection.queue_depth += 1);
+}
+
+pub fn message_dequeued(connection_id: u32) {
+    with_connection(connection_id, |connection| {
+        connection.queue_depth = connection.queue_depth.saturating_sub(1);
+    });
+}
+
+pub fn clear() {
+    get_connection_metrics().lock().unwrap().clear();
+}
+
+pub fn snapshot() -> ServerMetrics {
+    let metrics = get_connection_metrics().lock().unwrap();
+    let mut connections: Vec<ConnectionMetrics> = metrics.values().cloned().collect();
+    connections.sort_by_key(|c| c.connection_id);
+    ServerMetrics {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        connections,
+    }
+}
+
+// Periodically emit a `metrics` event with the current snapshot
+pub fn start_reporter(app_handle: AppHandle, every: Duration) -> async_runtime::JoinHandle<()> {
+    async_runtime::spawn(async move {
+        let mut interval = interval(every);
+
+        loop {
+            interval.tick().await;
+            let _ = app_handle.emit("metrics", &snapshot());
+        }
+    })
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Render the snapshot in the Prometheus text exposition format
+pub fn render_prometheus(metrics: &ServerMetrics) -> String {
+    let mut out = String::new();
+
+    let labels = |c: &ConnectionMetrics| {
+        format!(
+            "connection_id=\"{}\",client_id=\"{}\",name=\"{}\"",
+            c.connection_id,
+            escape_label(c.client_id.as_deref().unwrap_or("")),
+            escape_label(c.name.as_deref().unwrap_or(""))
+        )
+    };
+
+    let by_type: [(&str, &str, CounterField); 4] = [
+        ("reactauri_messages_in_total", "Commands received from a client.", |c| c.messages_in),
+        ("reactauri_bytes_in_total", "Bytes received from a client.", |c| c.bytes_in),
+        ("reactauri_messages_out_total", "Commands sent to a client.", |c| c.messages_out),
+        ("reactauri_bytes_out_total", "Bytes sent to a client.", |c| c.bytes_out),
+    ];
+    for (name, help, value) in by_type {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter", name, help, name);
+        for connection in &metrics.connections {
+            for (command_type, counters) in &connection.by_type {
+                let _ = writeln!(
+                    out,
+                    "{}{{{},type=\"{}\"}} {}",
+                    name,
+                    labels(connection),
+                    escape_label(command_type),
+                    value(counters)
+                );
+            }
+        }
+    }
+
+    let _ = writeln!(out, "# HELP reactauri_parse_failures_total Messages that could not be parsed as commands.");
+    let _ = writeln!(out, "# TYPE reactauri_parse_failures_total counter");
+    for connection in &metrics.connections {
+        let _ = writeln!(out, "reactauri_parse_failures_total{{{}}} {}", labels(connection), connection.parse_failures);
+    }
+
+    let _ = writeln!(out, "# HELP reactauri_throttled_total Commands dropped by rate limiting.");
+    let _ = writeln!(out, "# TYPE reactauri_throttled_total counter");
+    for connection in &metrics.connections {
+        let _ = writeln!(out, "reactauri_throttled_total{{{}}} {}", labels(connection), connection.throttled);
+    }
+
+    let compression: [(&str, &str, CompressionField); 4] = [
+        ("reactauri_compressed_wire_bytes_in_total", "Compressed bytes received from a client.", |c| c.wire_bytes_in),
+        ("reactauri_compressed_raw_bytes_in_total", "Inflated size of compressed messages from a client.", |c| c.raw_bytes_in),
+        ("reactauri_compressed_wire_bytes_out_total", "Compressed bytes sent to a client.", |c| c.wire_bytes_out),
+        ("reactauri_compressed_raw_bytes_out_total", "Size before compression of messages sent to a client.", |c| c.raw_bytes_out),
+    ];
+    for (name, help, value) in compression {
+        let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter", name, help, name);
+        for connection in metrics.connections.iter().filter(|c| c.compression.negotiated) {
+            let _ = writeln!(out, "{}{{{}}} {}", name, labels(connection), value(&connection.compression));
+        }
+    }
+
+    let _ = writeln!(out, "# HELP reactauri_queue_depth Messages waiting to be written to a client.");
+    let _ = writeln!(out, "# TYPE reactauri_queue_depth gauge");
+    for connection in &metrics.connections {
+        let _ = writeln!(out, "reactauri_queue_depth{{{}}} {}", labels(connection), connection.queue_depth);
+    }
+
+    let _ = writeln!(out, "# HELP reactauri_ping_rtt_milliseconds Last measured ping round trip time.");
+    let _ = writeln!(out, "# TYPE reactauri_ping_rtt_milliseconds gauge");
+    for connection in &metrics.connections {
+        if let Some(rtt) = connection.ping_rtt_ms {
+            let _ = writeln!(out, "reactauri_ping_rtt_milliseconds{{{}}} {}", labels(connection), rtt);
+        }
+    }
+
+    out
+}
+
+// Answer a request to the metrics endpoint, `request` being its first bytes
+fn respond(request: &str, metrics: impl FnOnce() -> ServerMetrics) -> String {
+    let mut request_line = request.lines().next().unwrap_or("").split(' ');
+    let method = request_line.next().unwrap_or("");
+    let path = request_line.next().unwrap_or("");
+    if path != "/metrics" && !path.starts_with("/metrics?") {
+        return "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string();
+    }
+    if method != "GET" {
+        return "HTTP/1.1 405 Method Not Allowed\r\nAllow: GET\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            .to_string();
+    }
+
+    let body = render_prometheus(&metrics());
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+// Serve `GET /metrics` in Prometheus text format on localhost
+pub fn start_prometheus_endpoint(port: u16) -> async_runtime::JoinHandle<()> {
+    async_runtime::spawn(async move {
+        let listener = match TcpListener::bind(("127.0.0.1", port)).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                warn!("Failed to start metrics endpoint on port {}: {}", port, e);
+                return;
+            }
+        };
+        info!("Metrics endpoint started: http://127.0.0.1:{}/metrics", port);
+
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                continue;
+            };
+
+            async_runtime::spawn(async move {
+                let mut buf = [0u8; 1024];
+                let read = stream.read(&mut buf).await.unwrap_or(0);
+                let response = respond(&String::from_utf8_lossy(&buf[..read]), snapshot);
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    })
+}
+
+#[tauri::command]
+pub fn get_server_metrics() -> ServerMetrics {
+    snapshot()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connection(client_id: Option<&str>, name: Option<&str>) -> ConnectionMetrics {
+        ConnectionMetrics {
+            connection_id: 7,
+            address: "127.0.0.1".to_string(),
+            client_id: client_id.map(str::to_string),
+            name: name.map(str::to_string),
+            connected_at: String::new(),
+            totals: CommandCounters::default(),
+            by_type: HashMap::from([(
+                "log".to_string(),
+                CommandCounters {
+                    messages_in: 3,
+                    bytes_in: 120,
+                    messages_out: 1,
+                    bytes_out: 40,
+                },
+            )]),
+            parse_failures: 2,
+            throttled: 0,
+            ping_rtt_ms: Some(1.5),
+            queue_depth: 4,
+            compression: CompressionMetrics::default(),
+            ping_sent_at: None,
+        }
+    }
+
+    fn metrics(connections: Vec<ConnectionMetrics>) -> ServerMetrics {
+        ServerMetrics {
+            timestamp: String::new(),
+            connections,
+        }
+    }
+
+    #[test]
+    fn renders_help_and_type_before_each_metric() {
+        let text = render_prometheus(&metrics(vec![connection(Some("a"), Some("app"))]));
+        let lines: Vec<&str> = text.lines().collect();
+
+        for (index, line) in lines.iter().enumerate() {
+            if let Some(help) = line.strip_prefix("# HELP ") {
+                let name = help.split(' ').next().unwrap();
+                let kind = if name.ends_with("_total") { "counter" } else { "gauge" };
+                assert_eq!(lines[index + 1], format!("# TYPE {} {}", name, kind));
+            } else if !line.starts_with("# TYPE ") {
+                let name = line.split('{').next().unwrap();
+                assert!(text.contains(&format!("# TYPE {} ", name)), "{} has no TYPE line", name);
+            }
+        }
+        assert!(lines.contains(&r#"reactauri_messages_in_total{connection_id="7",client_id="a",name="app",type="log"} 3"#));
+        assert!(lines.contains(&r#"reactauri_parse_failures_total{connection_id="7",client_id="a",name="app"} 2"#));
+        assert!(lines.contains(&r#"reactauri_ping_rtt_milliseconds{connection_id="7",client_id="a",name="app"} 1.5"#));
+        // Compression metrics are only listed for connections that agreed on it
+        assert!(!text.contains("reactauri_compressed_wire_bytes_in_total{"));
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        let text = render_prometheus(&metrics(vec![connection(None, Some("my \"app\"\\\nv2"))]));
+        assert!(text.contains(r#"client_id="",name="my \"app\"\\\nv2""#));
+        assert_eq!(escape_label("a\\b\"c\nd"), r#"a\\b\"c\nd"#);
+    }
+
+    #[test]
+    fn serves_metrics_on_get_only() {
+        let ok = respond("GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n", || metrics(Vec::new()));
+        assert!(ok.starts_with("HTTP/1.1 200 OK\r\n"));
+        let (head, body) = ok.split_once("\r\n\r\n").unwrap();
+        assert!(head.contains(&format!("Content-Length: {}\r\n", body.len())));
+        assert!(body.contains("# TYPE reactauri_queue_depth gauge"));
+        assert!(respond("GET /metrics?name[]=x HTTP/1.1\r\n", || metrics(Vec::new())).starts_with("HTTP/1.1 200 OK"));
+
+        let not_found = |request: &str| respond(request, || panic!("metrics rendered for {}", request));
+        assert!(not_found("GET / HTTP/1.1\r\n").starts_with("HTTP/1.1 404 Not Found"));
+        assert!(not_found("GET /metricsfoo HTTP/1.1\r\n").starts_with("HTTP/1.1 404 Not Found"));
+        assert!(not_found("").starts_with("HTTP/1.1 404 Not Found"));
+        assert!(not_found("POST /metrics HTTP/1.1\r\n").starts_with("HTTP/1.1 405 Method Not Allowed"));
+        assert!(not_found("HEAD /metrics HTTP/1.1\r\n").starts_with("HTTP/1.1 405 Method Not Allowed"));
+    }
+}
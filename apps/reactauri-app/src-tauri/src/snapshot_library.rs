@@ -0,0 +1,308 @@
+use crate::client_requests;
+use crate::reactauri_core_server::{self, CommandWithClientId};
+use crate::redaction;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use tauri::AppHandle;
+use tauri::Manager;
+use uuid::Uuid;
+
+// Snapshots live in `<app data>/snapshots`: an index with the metadata of
+// every snapshot, and one `<id>.json` file holding the state of each. States
+// are stored as the client sent them, so restoring one brings the app back
+// exactly; redaction rules apply when a state is shown or exported.
+const SNAPSHOT_DIR_NAME: &str = "snapshots";
+const INDEX_FILE_NAME: &str = "index.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotInfo {
+    pub id: String,
+    pub name: String,
+    pub client_id: Option<String>,
+    pub app_name: Option<String>,
+    pub created_at: String,
+    // Size of the serialized state in bytes
+    pub size: usize,
+}
+
+// Format of exported snapshot files
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SnapshotFile {
+    #[serde(flatten)]
+    info: SnapshotInfo,
+    state: Value,
+}
+
+// Loaded from disk on first use. Only taken on the blocking pool, see `blocking`.
+static SNAPSHOT_INDEX: OnceLock<Mutex<Option<Vec<SnapshotInfo>>>> = OnceLock::new();
+
+fn get_snapshot_index() -> &'static Mutex<Option<Vec<SnapshotInfo>>> {
+    SNAPSHOT_INDEX.get_or_init(|| Mutex::new(None))
+}
+
+// Run file I/O on the blocking pool, off the async runtime and the main thread
+async fn blocking<T, F>(f: F) -> Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, String> + Send + 'static,
+{
+    tauri::async_runtime::spawn_blocking(f)
+        .await
+        .map_err(|e| format!("Snapshot task failed: {}", e))?
+}
+
+fn snapshot_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| e.to_string())?
+        .join(SNAPSHOT_DIR_NAME);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    Ok(dir)
+}
+
+fn state_file(app: &AppHandle, id: &str) -> Result<PathBuf, String> {
+    if Uuid::parse_str(id).is_err() {
+        return Err(format!("Invalid snapshot id {}", id));
+    }
+    Ok(snapshot_dir(app)?.join(format!("{}.json", id)))
+}
+
+fn read_index(app: &AppHandle) -> Result<Vec<SnapshotInfo>, String> {
+    let path = snapshot_dir(app)?.join(INDEX_FILE_NAME);
+    match fs::read_to_string(&path) {
+        Ok(text) => serde_json::from_str(&text).map_err(|e| format!("Failed to read {}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+fn write_index(app: &AppHandle, index: &[SnapshotInfo]) -> Result<(), String> {
+    let path = snapshot_dir(app)?.join(INDEX_FILE_NAME);
+    let text = serde_json::to_string_pretty(index).map_err(|e| e.to_string())?;
+    fs::write(&path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+fn loaded_index<'a>(app: &AppHandle, index: &'a mut Option<Vec<SnapshotInfo>>) -> Result<&'a mut Vec<SnapshotInfo>, String> {
+    if index.is_none() {
+        *index = Some(read_index(app)?);
+    }
+    Ok(index.as_mut().unwrap())
+}
+
+fn read_snapshots<T, F: FnOnce(&[SnapshotInfo]) -> Result<T, String>>(app: &AppHandle, f: F) -> Result<T, String> {
+    let mut guard = get_snapshot_index().lock().unwrap();
+    f(loaded_index(app, &mut guard)?)
+}
+
+// Run `f` against the index, writing it back to disk when `f` succeeds
+fn with_index<T, F: FnOnce(&mut Vec<SnapshotInfo>) -> Result<T, String>>(app: &AppHandle, f: F) -> Result<T, String> {
+    let mut guard = get_snapshot_index().lock().unwrap();
+    let index = loaded_index(app, &mut guard)?;
+    let result = f(index)?;
+    write_index(app, index)?;
+    Ok(result)
+}
+
+fn default_name(app_name: Option<&str>) -> String {
+    format!(
+        "{} @ {}",
+        app_name.unwrap_or("Snapshot"),
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S")
+    )
+}
+
+fn insert(app: &AppHandle, mut info: SnapshotInfo, state: &Value) -> Result<SnapshotInfo, String> {
+    let text = serde_json::to_string(state).map_err(|e| e.to_string())?;
+    info.size = text.len();
+
+    let path = state_file(app, &info.id)?;
+    fs::write(&path, text).map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    with_index(app, |index| {
+        index.push(info.clone());
+        Ok(info)
+    })
+}
+
+// Add a snapshot to the library
+pub async fn save(
+    app: AppHandle,
+    name: Option<String>,
+    client_id: Option<String>,
+    app_name: Option<String>,
+    state: Value,
+) -> Result<SnapshotInfo, String> {
+    let info = SnapshotInfo {
+        id: Uuid::new_v4().to_string(),
+        name: name.unwrap_or_else(|| default_name(app_name.as_deref())),
+        client_id,
+        app_name,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        size: 0,
+    };
+    blocking(move || insert(&app, info, &state)).await
+}
+
+pub fn load_state(app: &AppHandle, id: &str) -> Result<Value, String> {
+    let path = state_file(app, id)?;
+    let text = fs::read_to_string(&path).map_err(|e| format!("Snapshot {} not found: {}", id, e))?;
+    serde_json::from_str(&text).map_err(|e| format!("Failed to read snapshot {}: {}", id, e))
+}
+
+pub fn find(app: &AppHandle, id: &str) -> Result<SnapshotInfo, String> {
+    read_snapshots(app, |index| {
+        index
+            .iter()
+            .find(|info| info.id == id)
+            .cloned()
+            .ok_or_else(|| format!("Snapshot {} not found", id))
+    })
+}
+
+// Send a stored snapshot to a client as `state.restore.request`
+pub async fn restore(app: AppHandle, id: String, client_id: String) -> Result<(), String> {
+    if !reactauri_core_server::is_client_connected(&client_id).await {
+        return Err(format!("Client {} is not connected", client_id));
+    }
+    let state = {
+        let app = app.clone();
+        blocking(move || load_state(&app, &id)).await?
+    };
+    let command = CommandWithClientId {
+        r#type: "state.restore.request".to_string(),
+        payload: serde_json::json!({ "state": state }),
+        client_id,
+        important: false,
+        date: Some(chrono::Utc::now().to_rfc3339()),
+        delta_time: Some(0),
+    };
+    reactauri_core_server::send_command(app, command).await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn list_snapshots(app: AppHandle) -> Result<Vec<SnapshotInfo>, String> {
+    blocking(move || {
+        read_snapshots(&app, |index| {
+            let mut snapshots = index.to_vec();
+            snapshots.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+            Ok(snapshots)
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn get_snapshot_state(app: AppHandle, id: String) -> Result<Value, String> {
+    blocking(move || load_state(&app, &id).map(|state| redaction::redact_state(&state))).await
+}
+
+// Ask a client for a backup of its state and store it under `name`
+#[tauri::command]
+pub async fn create_snapshot(
+    app: AppHandle,
+    client_id: String,
+    name: Option<String>,
+    timeout_ms: Option<u64>,
+) -> Result<SnapshotInfo, String> {
+    let timeout = timeout_ms
+        .map(std::time::Duration::from_millis)
+        .unwrap_or(client_requests::DEFAULT_TIMEOUT);
+    let mut payload = client_requests::request(
+        app.clone(),
+        &client_id,
+        "state.backup.request",
+        serde_json::json!({}),
+        "state.backup.response",
+        None,
+        timeout,
+    )
+    .await?;
+
+    let state = payload
+        .get_mut("state")
+        .map(Value::take)
+        .ok_or_else(|| format!("Client {} sent a backup without state", client_id))?;
+    let app_name = reactauri_core_server::get_client_name(&client_id).await;
+    save(app, name, Some(client_id), app_name, state).await
+}
+
+#[tauri::command]
+pub async fn rename_snapshot(app: AppHandle, id: String, name: String) -> Result<SnapshotInfo, String> {
+    blocking(move || {
+        with_index(&app, |index| {
+            let info = index
+                .iter_mut()
+                .find(|info| info.id == id)
+                .ok_or_else(|| format!("Snapshot {} not found", id))?;
+            info.name = name;
+            Ok(info.clone())
+        })
+    })
+    .await
+}
+
+#[tauri::command]
+pub async fn delete_snapshot(app: AppHandle, id: String) -> Result<(), String> {
+    blocking(move || delete(&app, &id)).await
+}
+
+fn delete(app: &AppHandle, id: &str) -> Result<(), String> {
+    let path = state_file(app, id)?;
+    with_index(app, |index| {
+        let before = index.len();
+        index.retain(|info| info.id != id);
+        if index.len() == before {
+            return Err(format!("Snapshot {} not found", id));
+        }
+        Ok(())
+    })?;
+    match fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("Failed to delete {}: {}", path.display(), e))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[tauri::command]
+pub async fn export_snapshot(app: AppHandle, id: String, destination: String) -> Result<(), String> {
+    blocking(move || export(&app, &id, &destination)).await
+}
+
+fn export(app: &AppHandle, id: &str, destination: &str) -> Result<(), String> {
+    let file = SnapshotFile {
+        info: find(app, id)?,
+        state: redaction::redact_state(&load_state(app, id)?),
+    };
+    let text = serde_json::to_string_pretty(&file).map_err(|e| e.to_string())?;
+    fs::write(destination, text).map_err(|e| format!("Failed to export snapshot to {}: {}", destination, e))
+}
+
+// Import a file written by `export_snapshot`, as a new snapshot
+#[tauri::command]
+pub async fn import_snapshot(app: AppHandle, source: String) -> Result<SnapshotInfo, String> {
+    blocking(move || import(&app, &source)).await
+}
+
+fn import(app: &AppHandle, source: &str) -> Result<SnapshotInfo, String> {
+    let text = fs::read_to_string(source).map_err(|e| format!("Failed to read {}: {}", source, e))?;
+    let file: SnapshotFile =
+        serde_json::from_str(&text).map_err(|e| format!("{} is not a snapshot file: {}", source, e))?;
+
+    let info = SnapshotInfo {
+        id: Uuid::new_v4().to_string(),
+        ..file.info
+    };
+    insert(app, info, &file.state)
+}
+
+#[tauri::command]
+pub async fn restore_snapshot(app: AppHandle, id: String, client_id: String) -> Result<(), String> {
+    restore(app, id, client_id).await
+}
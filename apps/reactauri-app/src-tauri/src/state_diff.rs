@@ -0,0 +1,435 @@
+use crate::client_requests;
+use crate::redaction;
+use crate::snapshot_library;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::time::Duration;
+use tauri::AppHandle;
+
+// Entries reported before a diff is cut short
+const MAX_ENTRIES: usize = 10_000;
+// Arrays whose LCS table would be larger than this are compared index by index
+const MAX_LCS_CELLS: usize = 4_000_000;
+
+// One difference between two states. Paths use the dot notation of
+// `state.values.request` (`todos.3.title`), the root being "".
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum DiffEntry {
+    #[serde(rename_all = "camelCase")]
+    Added { path: String, new_value: Value },
+    #[serde(rename_all = "camelCase")]
+    Removed { path: String, old_value: Value },
+    #[serde(rename_all = "camelCase")]
+    Changed { path: String, old_value: Value, new_value: Value },
+    // An array element that kept its value but changed position
+    #[serde(rename_all = "camelCase")]
+    Moved { path: String, from: usize, to: usize, value: Value },
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateDiff {
+    pub entries: Vec<DiffEntry>,
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub moved: usize,
+    // Set when the diff stopped after `MAX_ENTRIES` entries
+    pub truncated: bool,
+}
+
+impl StateDiff {
+    fn push(&mut self, entry: DiffEntry) {
+        if self.entries.len() >= MAX_ENTRIES {
+            self.truncated = true;
+            return;
+        }
+        match entry {
+            DiffEntry::Added { .. } => self.added += 1,
+            DiffEntry::Removed { .. } => self.removed += 1,
+            DiffEntry::Changed { .. } => self.changed += 1,
+            DiffEntry::Moved { .. } => self.moved += 1,
+        }
+        self.entries.push(entry);
+    }
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+// Structural diff of two JSON values
+pub fn diff(old: &Value, new: &Value) -> StateDiff {
+    diff_at(old, new, "")
+}
+
+// Same as `diff`, for values found at `path`, which prefixes the reported paths
+pub fn diff_at(old: &Value, new: &Value, path: &str) -> StateDiff {
+    let mut result = StateDiff::default();
+    diff_values(old, new, path, &mut result);
+    result
+}
+
+fn diff_values(old: &Value, new: &Value, path: &str, result: &mut StateDiff) {
+    if result.truncated || old == new {
+        return;
+    }
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => diff_objects(old, new, path, result),
+        (Value::Array(old), Value::Array(new)) => diff_arrays(old, new, path, result),
+        _ => result.push(DiffEntry::Changed {
+            path: path.to_string(),
+            old_value: old.clone(),
+            new_value: new.clone(),
+        }),
+    }
+}
+
+fn diff_objects(old: &Map<String, Value>, new: &Map<String, Value>, path: &str, result: &mut StateDiff) {
+    for (key, old_value) in old {
+        match new.get(key) {
+            Some(new_value) => diff_values(old_value, new_value, &child_path(path, key), result),
+            None => result.push(DiffEntry::Removed {
+                path: child_path(path, key),
+                old_value: old_value.clone(),
+            }),
+        }
+    }
+    for (key, new_value) in new {
+        if !old.contains_key(key) {
+            result.push(DiffEntry::Added {
+                path: child_path(path, key),
+                new_value: new_value.clone(),
+            });
+        }
+    }
+}
+
+// Pairs of (old index, new index) of a longest common subsequence
+fn longest_common_subsequence(old: &[Value], new: &[Value]) -> Vec<(usize, usize)> {
+    let (n, m) = (old.len(), new.len());
+    let mut lengths = vec![0u32; (n + 1) * (m + 1)];
+    let at = |i: usize, j: usize| i * (m + 1) + j;
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[at(i, j)] = if old[i] == new[j] {
+                lengths[at(i + 1, j + 1)] + 1
+            } else {
+                lengths[at(i + 1, j)].max(lengths[at(i, j + 1)])
+            };
+        }
+    }
+
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i] == new[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[at(i + 1, j)] >= lengths[at(i, j + 1)] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+fn diff_arrays(old: &[Value], new: &[Value], path: &str, result: &mut StateDiff) {
+    // Elements at the start and end that did not change are left out of the LCS
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    if (old_middle.len() + 1) * (new_middle.len() + 1) > MAX_LCS_CELLS {
+        diff_arrays_by_index(old, new, path, result);
+        return;
+    }
+
+    // Anchors: elements kept in place, plus sentinels closing the last gap
+    let mut anchors: Vec<(usize, usize)> = longest_common_subsequence(old_middle, new_middle)
+        .into_iter()
+        .map(|(i, j)| (i + prefix, j + prefix))
+        .collect();
+    anchors.push((old.len() - suffix, new.len() - suffix));
+
+    // Elements between anchors were removed, added or changed
+    let mut gaps = Vec::new();
+    let (mut old_start, mut new_start) = (prefix, prefix);
+    for (old_anchor, new_anchor) in anchors {
+        gaps.push((old_start..old_anchor, new_start..new_anchor));
+        old_start = old_anchor + 1;
+        new_start = new_anchor + 1;
+    }
+
+    // An element that disappeared from one gap and shows up unchanged in another moved
+    let mut moved_old = vec![false; old.len()];
+    let mut moved_new = vec![false; new.len()];
+    for (old_range, _) in &gaps {
+        for i in old_range.clone() {
+            let target = gaps
+                .iter()
+                .flat_map(|(_, new_range)| new_range.clone())
+                .find(|&j| !moved_new[j] && old[i] == new[j]);
+            if let Some(j) = target {
+                moved_old[i] = true;
+                moved_new[j] = true;
+                result.push(DiffEntry::Moved {
+                    path: path.to_string(),
+                    from: i,
+                    to: j,
+                    value: new[j].clone(),
+                });
+            }
+        }
+    }
+
+    // What is left in each gap is paired up by position and compared
+    for (old_range, new_range) in gaps {
+        let removed: Vec<usize> = old_range.filter(|&i| !moved_old[i]).collect();
+        let added: Vec<usize> = new_range.filter(|&j| !moved_new[j]).collect();
+        for (&i, &j) in removed.iter().zip(&added) {
+            diff_values(&old[i], &new[j], &child_path(path, &j.to_string()), result);
+        }
+        for &i in removed.iter().skip(added.len()) {
+            result.push(DiffEntry::Removed {
+                path: child_path(path, &i.to_string()),
+                old_value: old[i].clone(),
+            });
+        }
+        for &j in added.iter().skip(removed.len()) {
+            result.push(DiffEntry::Added {
+                path: child_path(path, &j.to_string()),
+                new_value: new[j].clone(),
+            });
+        }
+    }
+}
+
+fn diff_arrays_by_index(old: &[Value], new: &[Value], path: &str, result: &mut StateDiff) {
+    for (i, (old_value, new_value)) in old.iter().zip(new).enumerate() {
+        diff_values(old_value, new_value, &child_path(path, &i.to_string()), result);
+    }
+    for (i, old_value) in old.iter().enumerate().skip(new.len()) {
+        result.push(DiffEntry::Removed {
+            path: child_path(path, &i.to_string()),
+            old_value: old_value.clone(),
+        });
+    }
+    for (i, new_value) in new.iter().enumerate().skip(old.len()) {
+        result.push(DiffEntry::Added {
+            path: child_path(path, &i.to_string()),
+            new_value: new_value.clone(),
+        });
+    }
+}
+
+// A side of a diff: a stored snapshot, a `state.backup.response` payload or a
+// `state.values.response` payload
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum DiffSource {
+    #[serde(rename_all = "camelCase")]
+    Snapshot { snapshot_id: String },
+    Backup { state: Value },
+    Values { value: Value },
+}
+
+impl DiffSource {
+    fn resolve(self, app: &AppHandle) -> Result<Value, String> {
+        match self {
+            Self::Snapshot { snapshot_id } => {
+                snapshot_library::load_state(app, &snapshot_id).map(|state| redaction::redact_state(&state))
+            }
+            Self::Backup { state } => Ok(state),
+            Self::Values { value } => Ok(value),
+        }
+    }
+}
+
+// Snapshots are read from disk and large states take a while to diff, so
+// both run on the blocking pool
+#[tauri::command]
+pub async fn diff_states(app: AppHandle, before: DiffSource, after: DiffSource) -> Result<StateDiff, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let before = before.resolve(&app)?;
+        let after = after.resolve(&app)?;
+        Ok(diff(&before, &after))
+    })
+    .await
+    .map_err(|e| format!("State diff failed: {}", e))?
+}
+
+// How a state path differs between two clients
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathComparison {
+    pub path: String,
+    // Whether each client has something at this path
+    pub left_valid: bool,
+    pub right_valid: bool,
+    pub diff: Option<StateDiff>,
+    // Set when a client did not answer, the diff is left out then
+    pub error: Option<String>,
+}
+
+// Ask two clients for the same state paths at once and diff their answers,
+// the whole state when no path is given
+#[tauri::command]
+pub async fn compare_clients(
+    app: AppHandle,
+    left_client_id: String,
+    right_client_id: String,
+    paths: Vec<String>,
+    timeout_ms: Option<u64>,
+) -> Result<Vec<PathComparison>, String> {
+    if left_client_id == right_client_id {
+        return Err("Pick two different clients to compare".to_string());
+    }
+    let timeout = timeout_ms
+        .map(Duration::from_millis)
+        .unwrap_or(client_requests::DEFAULT_TIMEOUT);
+    let paths = if paths.is_empty() { vec![String::new()] } else { paths };
+
+    let comparisons = paths.into_iter().map(|path| {
+        let app = app.clone();
+        let (left_client_id, right_client_id) = (&left_client_id, &right_client_id);
+        async move {
+            let (left, right) = futures_util::join!(
+                client_requests::request_state_values_from(app.clone(), left_client_id, Some(path.clone()), timeout),
+                client_requests::request_state_values_from(app, right_client_id, Some(path.clone()), timeout),
+            );
+            let left = left.and_then(|l| client_requests::redacted("state.values.response", l));
+            let right = right.and_then(|r| client_requests::redacted("state.values.response", r));
+            match (left, right) {
+                (Ok(left), Ok(right)) => {
+                    let (left_valid, right_valid) = (left.valid, right.valid);
+                    let diff_path = path.clone();
+                    let diff = tauri::async_runtime::spawn_blocking(move || diff_at(&left.value, &right.value, &diff_path))
+                        .await
+                        .map_err(|e| format!("State diff failed: {}", e));
+                    PathComparison {
+                        path,
+                        left_valid,
+                        right_valid,
+                        error: diff.as_ref().err().cloned(),
+                        diff: diff.ok(),
+                    }
+                }
+                (left, right) => PathComparison {
+                    path,
+                    left_valid: left.as_ref().is_ok_and(|l| l.valid),
+                    right_valid: right.as_ref().is_ok_and(|r| r.valid),
+                    diff: None,
+                    error: left.err().or(right.err()),
+                },
+            }
+        }
+    });
+    Ok(join_all(comparisons).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entries(diff: &StateDiff) -> Value {
+        serde_json::to_value(&diff.entries).unwrap()
+    }
+
+    #[test]
+    fn objects_are_compared_key_by_key() {
+        let result = diff(&json!({ "a": 1, "b": { "c": 2 }, "d": 3 }), &json!({ "a": 1, "b": { "c": 4 }, "e": 5 }));
+        assert_eq!(
+            entries(&result),
+            json!([
+                { "kind": "changed", "path": "b.c", "oldValue": 2, "newValue": 4 },
+                { "kind": "removed", "path": "d", "oldValue": 3 },
+                { "kind": "added", "path": "e", "newValue": 5 },
+            ])
+        );
+        assert_eq!((result.added, result.removed, result.changed, result.moved), (1, 1, 1, 0));
+        assert!(diff(&json!({ "a": [1, { "b": 2 }] }), &json!({ "a": [1, { "b": 2 }] })).entries.is_empty());
+    }
+
+    #[test]
+    fn longest_common_subsequence_pairs_indexes() {
+        let old = [json!(1), json!(2), json!(3), json!(4)];
+        let new = [json!(2), json!(4), json!(5)];
+        assert_eq!(longest_common_subsequence(&old, &new), [(1, 0), (3, 1)]);
+        assert!(longest_common_subsequence(&old, &[]).is_empty());
+    }
+
+    #[test]
+    fn array_insertions_do_not_shift_later_elements() {
+        let result = diff(&json!(["a", "b", "c"]), &json!(["a", "x", "b", "c"]));
+        assert_eq!(entries(&result), json!([{ "kind": "added", "path": "1", "newValue": "x" }]));
+
+        let result = diff(&json!({ "todos": ["a", "b", "c"] }), &json!({ "todos": ["a", "c"] }));
+        assert_eq!(entries(&result), json!([{ "kind": "removed", "path": "todos.1", "oldValue": "b" }]));
+    }
+
+    #[test]
+    fn array_elements_that_changed_position_are_moves() {
+        let result = diff(&json!([1, 2, 3, 4, 5]), &json!([1, 3, 4, 2, 5]));
+        assert_eq!(entries(&result), json!([{ "kind": "moved", "path": "", "from": 1, "to": 3, "value": 2 }]));
+        assert_eq!(result.moved, 1);
+    }
+
+    #[test]
+    fn array_elements_left_in_a_gap_are_compared() {
+        let old = json!([{ "id": 1, "title": "a" }, { "id": 2, "title": "b" }]);
+        let new = json!([{ "id": 1, "title": "a" }, { "id": 2, "title": "c" }, { "id": 3 }]);
+        assert_eq!(
+            entries(&diff(&old, &new)),
+            json!([
+                { "kind": "changed", "path": "1.title", "oldValue": "b", "newValue": "c" },
+                { "kind": "added", "path": "2", "newValue": { "id": 3 } },
+            ])
+        );
+    }
+
+    #[test]
+    fn diff_at_prefixes_paths() {
+        assert_eq!(
+            entries(&diff_at(&json!({ "x": [1] }), &json!({ "x": [2] }), "user")),
+            json!([{ "kind": "changed", "path": "user.x.0", "oldValue": 1, "newValue": 2 }])
+        );
+        assert_eq!(
+            entries(&diff(&json!(1), &json!("1"))),
+            json!([{ "kind": "changed", "path": "", "oldValue": 1, "newValue": "1" }])
+        );
+    }
+
+    #[test]
+    fn large_arrays_are_compared_by_index() {
+        let old: Vec<Value> = (0..2100).map(Value::from).collect();
+        let new: Vec<Value> = (1..2101).map(Value::from).collect();
+        let result = diff(&Value::Array(old), &Value::Array(new));
+        assert_eq!(result.changed, 2100);
+        assert_eq!(result.added + result.removed + result.moved, 0);
+    }
+
+    #[test]
+    fn long_diffs_are_truncated() {
+        let new: Map<String, Value> = (0..MAX_ENTRIES + 5).map(|i| (i.to_string(), Value::from(i))).collect();
+        let result = diff(&json!({}), &Value::Object(new));
+        assert!(result.truncated);
+        assert_eq!(result.entries.len(), MAX_ENTRIES);
+        assert_eq!(result.added, MAX_ENTRIES);
+    }
+}
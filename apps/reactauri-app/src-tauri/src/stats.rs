@@ -0,0 +1,69 @@
+use serde::Serialize;
+
+// Summary of a set of measurements
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Distribution {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+impl Distribution {
+    pub fn from_values(mut values: Vec<f64>) -> Self {
+        values.retain(|v| v.is_finite());
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_by(f64::total_cmp);
+        Self {
+            count: values.len(),
+            min: values[0],
+            max: values[values.len() - 1],
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            p50: percentile(&values, 50.0),
+            p90: percentile(&values, 90.0),
+            p95: percentile(&values, 95.0),
+            p99: percentile(&values, 99.0),
+        }
+    }
+}
+
+// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentile_uses_the_nearest_rank() {
+        let values: Vec<f64> = (1..=10).map(f64::from).collect();
+        assert_eq!(percentile(&values, 50.0), 5.0);
+        assert_eq!(percentile(&values, 90.0), 9.0);
+        assert_eq!(percentile(&values, 95.0), 10.0);
+        assert_eq!(percentile(&values, 0.0), 1.0);
+        assert_eq!(percentile(&values, 100.0), 10.0);
+        assert_eq!(percentile(&[7.0], 99.0), 7.0);
+    }
+
+    #[test]
+    fn distribution_skips_values_that_are_not_finite() {
+        let distribution = Distribution::from_values(vec![3.0, f64::NAN, 1.0, f64::INFINITY, 2.0]);
+        assert_eq!(distribution.count, 3);
+        assert_eq!(distribution.min, 1.0);
+        assert_eq!(distribution.max, 3.0);
+        assert_eq!(distribution.mean, 2.0);
+        assert_eq!(distribution.p50, 2.0);
+        assert_eq!(distribution.p99, 3.0);
+        assert_eq!(Distribution::from_values(vec![f64::NAN]).count, 0);
+    }
+}
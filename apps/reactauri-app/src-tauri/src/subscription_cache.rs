@@ -0,0 +1,207 @@
+use crate::payload_store::json_size;
+use crate::redaction;
+use crate::state_diff::{self, DiffEntry};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use tauri::AppHandle;
+use tauri::Emitter;
+
+// Values kept per subscribed path, the oldest are dropped first
+const MAX_HISTORY_ENTRIES: usize = 200;
+const MAX_HISTORY_BYTES: usize = 16 * 1024 * 1024;
+// Diff entries included per path in a `stateValuesChanged` event
+const MAX_EVENT_ENTRIES: usize = 20;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub date: String,
+    pub value: Value,
+    pub size: usize,
+}
+
+#[derive(Default)]
+struct CachedPath {
+    history: VecDeque<HistoryEntry>,
+    history_bytes: usize,
+}
+
+impl CachedPath {
+    fn last_value(&self) -> Option<&Value> {
+        self.history.back().map(|entry| &entry.value)
+    }
+
+    fn push(&mut self, entry: HistoryEntry) {
+        self.history_bytes += entry.size;
+        self.history.push_back(entry);
+        // Always keep the latest value, it is what changes are compared against
+        while self.history.len() > 1
+            && (self.history.len() > MAX_HISTORY_ENTRIES || self.history_bytes > MAX_HISTORY_BYTES)
+        {
+            if let Some(dropped) = self.history.pop_front() {
+                self.history_bytes -= dropped.size;
+            }
+        }
+    }
+}
+
+// How one subscribed path changed
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathChange {
+    pub path: String,
+    // First value seen for this path, nothing to compare against
+    pub initial: bool,
+    pub size_before: Option<usize>,
+    pub size_after: usize,
+    pub added: usize,
+    pub removed: usize,
+    pub changed: usize,
+    pub moved: usize,
+    // The first few differences, see `state_diff`
+    pub entries: Vec<DiffEntry>,
+    pub truncated: bool,
+}
+
+// Payload of the `stateValuesChanged` event
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateValuesChanged {
+    pub client_id: String,
+    pub date: String,
+    pub changes: Vec<PathChange>,
+}
+
+type ClientPaths = HashMap<String, CachedPath>;
+
+static SUBSCRIPTION_CACHE: OnceLock<Mutex<HashMap<String, ClientPaths>>> = OnceLock::new();
+
+fn get_subscription_cache() -> &'static Mutex<HashMap<String, ClientPaths>> {
+    SUBSCRIPTION_CACHE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// Compare the values of a `state.values.change` payload with the cached ones,
+// store the new values and emit a `stateValuesChanged` event for those that differ
+pub fn record_changes(app_handle: &AppHandle, client_id: &str, payload: &Value) {
+    // The cache is only read by the UI
+    let payload = redaction::redact_copy("state.values.change", payload);
+    let Some(changes) = payload.get("changes").and_then(|c| c.as_array()) else {
+        return;
+    };
+    let date = chrono::Utc::now().to_rfc3339();
+
+    let mut cache = get_subscription_cache().lock().unwrap();
+    let paths = cache.entry(client_id.to_string()).or_default();
+
+    let mut path_changes = Vec::new();
+    for change in changes {
+        let path = change.get("path").and_then(|p| p.as_str()).unwrap_or("");
+        let value = change.get("value").cloned().unwrap_or(Value::Null);
+        let cached = paths.entry(path.to_string()).or_default();
+
+        let size_after = json_size(&value);
+        let path_change = match cached.last_value() {
+            Some(previous) if *previous == value => continue,
+            Some(previous) => {
+                let diff = state_diff::diff(previous, &value);
+                PathChange {
+                    path: path.to_string(),
+                    initial: false,
+                    size_before: cached.history.back().map(|entry| entry.size),
+                    size_after,
+                    added: diff.added,
+                    removed: diff.removed,
+                    changed: diff.changed,
+                    moved: diff.moved,
+                    truncated: diff.truncated || diff.entries.len() > MAX_EVENT_ENTRIES,
+                    entries: diff.entries.into_iter().take(MAX_EVENT_ENTRIES).collect(),
+                }
+            }
+            None => PathChange {
+                path: path.to_string(),
+                initial: true,
+                size_before: None,
+                size_after,
+                added: 0,
+                removed: 0,
+                changed: 0,
+                moved: 0,
+                entries: Vec::new(),
+                truncated: false,
+            },
+        };
+
+        cached.push(HistoryEntry {
+            date: date.clone(),
+            value,
+            size: size_after,
+        });
+        path_changes.push(path_change);
+    }
+
+    if path_changes.is_empty() {
+        return;
+    }
+    let _ = app_handle.emit(
+        "stateValuesChanged",
+        &StateValuesChanged {
+            client_id: client_id.to_string(),
+            date,
+            changes: path_changes,
+        },
+    );
+}
+
+pub fn remove_client(client_id: &str) {
+    get_subscription_cache().lock().unwrap().remove(client_id);
+}
+
+pub fn clear() {
+    get_subscription_cache().lock().unwrap().clear();
+}
+
+// Latest known value of every subscribed path of a client
+#[tauri::command]
+pub fn get_subscribed_values(client_id: String) -> HashMap<String, Value> {
+    let cache = get_subscription_cache().lock().unwrap();
+    cache
+        .get(&client_id)
+        .map(|paths| {
+            paths
+                .iter()
+                .filter_map(|(path, cached)| Some((path.clone(), cached.last_value()?.clone())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Values a subscribed path went through, oldest first
+#[tauri::command]
+pub fn get_path_history(client_id: String, path: String) -> Vec<HistoryEntry> {
+    let cache = get_subscription_cache().lock().unwrap();
+    cache
+        .get(&client_id)
+        .and_then(|paths| paths.get(&path))
+        .map(|cached| cached.history.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+// Forget the history of one path, or of every path, keeping the latest values
+#[tauri::command]
+pub fn clear_path_history(client_id: String, path: Option<String>) {
+    let mut cache = get_subscription_cache().lock().unwrap();
+    let Some(paths) = cache.get_mut(&client_id) else {
+        return;
+    };
+    for (cached_path, cached) in paths.iter_mut() {
+        if path.as_ref().is_none_or(|path| path == cached_path) {
+            if let Some(latest) = cached.history.pop_back() {
+                cached.history.clear();
+                cached.history_bytes = 0;
+                cached.push(latest);
+            }
+        }
+    }
+}
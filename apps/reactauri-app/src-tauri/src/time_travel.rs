@@ -0,0 +1,646 @@
+use crate::action_scripts::{self, ActionScript, ReplayOptions, ReplayResult, ScriptAction};
+use crate::client_requests;
+use crate::payload_store::json_size;
+use crate::reactauri_core_server::{self, Command, CommandWithClientId};
+use crate::redaction;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tauri::async_runtime;
+use tauri::AppHandle;
+use tokio::time::interval;
+
+// How often the snapshotter looks for clients due a backup
+const SNAPSHOTTER_TICK: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeTravelOptions {
+    pub enabled: bool,
+    // Ask for a backup this often while a client keeps dispatching actions
+    #[serde(default = "default_snapshot_interval_ms")]
+    pub snapshot_interval_ms: u64,
+    // ... or after this many actions, whichever comes first
+    #[serde(default = "default_snapshot_every_actions")]
+    pub snapshot_every_actions: usize,
+    // Kept per client, the oldest are dropped first
+    #[serde(default = "default_max_actions")]
+    pub max_actions: usize,
+    #[serde(default = "default_max_checkpoints")]
+    pub max_checkpoints: usize,
+}
+
+fn default_snapshot_interval_ms() -> u64 {
+    30_000
+}
+
+fn default_snapshot_every_actions() -> usize {
+    100
+}
+
+fn default_max_actions() -> usize {
+    5000
+}
+
+fn default_max_checkpoints() -> usize {
+    20
+}
+
+impl Default for TimeTravelOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            snapshot_interval_ms: default_snapshot_interval_ms(),
+            snapshot_every_actions: default_snapshot_every_actions(),
+            max_actions: default_max_actions(),
+            max_checkpoints: default_max_checkpoints(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedAction {
+    pub seq: u64,
+    pub date: String,
+    pub name: String,
+    pub action: Value,
+}
+
+// State of a client before the action numbered `position`
+#[derive(Debug, Clone)]
+struct Checkpoint {
+    position: u64,
+    date: String,
+    state: Value,
+    size: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckpointInfo {
+    pub position: u64,
+    pub date: String,
+    pub size: usize,
+}
+
+#[derive(Default)]
+struct Timeline {
+    next_seq: u64,
+    actions: VecDeque<RecordedAction>,
+    checkpoints: VecDeque<Checkpoint>,
+    last_checkpoint_at: Option<Instant>,
+    backup_pending: bool,
+    // Set while the client is being restored, its actions are not recorded then
+    traveling: bool,
+}
+
+impl Timeline {
+    fn actions_since_checkpoint(&self) -> u64 {
+        let position = self.checkpoints.back().map(|c| c.position).unwrap_or(0);
+        self.next_seq - position
+    }
+
+    fn add_action(&mut self, name: &str, action: Value, options: &TimeTravelOptions) {
+        self.actions.push_back(RecordedAction {
+            seq: self.next_seq,
+            date: chrono::Utc::now().to_rfc3339(),
+            name: name.to_string(),
+            action,
+        });
+        self.next_seq += 1;
+        self.trim(options);
+    }
+
+    fn add_checkpoint(&mut self, state: Value, options: &TimeTravelOptions) {
+        self.checkpoints.push_back(Checkpoint {
+            position: self.next_seq,
+            date: chrono::Utc::now().to_rfc3339(),
+            size: json_size(&state),
+            state,
+        });
+        self.last_checkpoint_at = Some(Instant::now());
+        self.trim(options);
+    }
+
+    fn trim(&mut self, options: &TimeTravelOptions) {
+        while self.checkpoints.len() > options.max_checkpoints.max(1) {
+            self.checkpoints.pop_front();
+        }
+        // Actions older than the first checkpoint can't be replayed anymore
+        let first_position = self.checkpoints.front().map(|c| c.position).unwrap_or(0);
+        while self
+            .actions
+            .front()
+            .is_some_and(|a| a.seq < first_position || self.actions.len() > options.max_actions.max(1))
+        {
+            self.actions.pop_front();
+        }
+        // ... and checkpoints older than the first action can't be replayed from
+        let first_seq = self.actions.front().map(|a| a.seq).unwrap_or(self.next_seq);
+        while self.checkpoints.front().is_some_and(|c| c.position < first_seq) {
+            self.checkpoints.pop_front();
+        }
+    }
+
+    // The checkpoint to restore and the actions to dispatch to reach the
+    // state right after action `seq`
+    fn plan(&self, seq: u64) -> Result<(Checkpoint, Vec<RecordedAction>), String> {
+        if seq >= self.next_seq {
+            return Err(format!("Action {} was not recorded", seq));
+        }
+        let checkpoint = self
+            .checkpoints
+            .iter()
+            .rev()
+            .find(|c| c.position <= seq + 1)
+            .ok_or_else(|| format!("No snapshot was taken before action {}", seq))?;
+        let actions: Vec<RecordedAction> = self
+            .actions
+            .iter()
+            .filter(|a| a.seq >= checkpoint.position && a.seq <= seq)
+            .cloned()
+            .collect();
+        if actions.len() as u64 != seq + 1 - checkpoint.position {
+            return Err(format!("Actions between the last snapshot and action {} were dropped", seq));
+        }
+        Ok((checkpoint.clone(), actions))
+    }
+
+    // Forget what came after action `seq`, the client starts a new branch from there
+    fn truncate_after(&mut self, seq: u64) {
+        self.actions.retain(|a| a.seq <= seq);
+        self.checkpoints.retain(|c| c.position <= seq + 1);
+        self.next_seq = seq + 1;
+    }
+}
+
+#[derive(Default)]
+struct TimeTravel {
+    options: TimeTravelOptions,
+    timelines: HashMap<String, Timeline>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelineInfo {
+    pub client_id: String,
+    pub actions: Vec<RecordedAction>,
+    pub checkpoints: Vec<CheckpointInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TravelResult {
+    pub client_id: String,
+    // Action the client was brought back to
+    pub seq: u64,
+    pub checkpoint: CheckpointInfo,
+    pub replay: ReplayResult,
+}
+
+static TIME_TRAVEL: OnceLock<Mutex<TimeTravel>> = OnceLock::new();
+
+fn get_time_travel() -> &'static Mutex<TimeTravel> {
+    TIME_TRAVEL.get_or_init(|| Mutex::new(TimeTravel::default()))
+}
+
+// Ask a client for a backup, returning its state
+async fn request_backup(app_handle: AppHandle, client_id: String) -> Option<Value> {
+    let result = client_requests::request(
+        app_handle,
+        &client_id,
+        "state.backup.request",
+        serde_json::json!({}),
+        "state.backup.response",
+        None,
+        client_requests::DEFAULT_TIMEOUT,
+    )
+    .await;
+    if let Some(timeline) = get_time_travel().lock().unwrap().timelines.get_mut(&client_id) {
+        timeline.backup_pending = false;
+    }
+    match result {
+        Ok(mut payload) => payload.get_mut("state").map(Value::take),
+        Err(e) => {
+            debug!("Time travel backup of client {} failed: {}", client_id, e);
+            None
+        }
+    }
+}
+
+// Ask a client for a backup in the background, which `observe` turns into a checkpoint
+fn spawn_backup(app_handle: &AppHandle, client_id: &str, timeline: &mut Timeline) {
+    timeline.backup_pending = true;
+    spawn_request_backup(app_handle, client_id);
+}
+
+fn spawn_request_backup(app_handle: &AppHandle, client_id: &str) {
+    let app_handle = app_handle.clone();
+    let client_id = client_id.to_string();
+    async_runtime::spawn(async move {
+        request_backup(app_handle, client_id).await;
+    });
+}
+
+impl TimeTravel {
+    // Record the actions and backups of a client as they arrived, unredacted,
+    // as they are sent back to it. Returns true when a backup should be taken.
+    fn record(&mut self, client_id: &str, cmd: &Command) -> bool {
+        let TimeTravel { options, timelines } = self;
+        if !options.enabled {
+            return false;
+        }
+
+        match cmd.r#type.as_str() {
+            // A new client, or a reloaded one, starts from a fresh state
+            "client.intro" => {
+                let timeline = timelines.entry(client_id.to_string()).or_default();
+                *timeline = Timeline::default();
+                timeline.backup_pending = true;
+                true
+            }
+            "state.action.complete" => {
+                let timeline = timelines.entry(client_id.to_string()).or_default();
+                if timeline.traveling {
+                    return false;
+                }
+                let Some(action) = cmd.payload.get("action") else {
+                    return false;
+                };
+                let name = cmd.payload.get("name").and_then(|n| n.as_str()).unwrap_or("");
+                timeline.add_action(name, action.clone(), options);
+
+                let due = !timeline.backup_pending
+                    && (timeline.checkpoints.is_empty()
+                        || timeline.actions_since_checkpoint() >= options.snapshot_every_actions.max(1) as u64);
+                if due {
+                    timeline.backup_pending = true;
+                }
+                due
+            }
+            "state.backup.response" => {
+                let Some(state) = cmd.payload.get("state") else {
+                    return false;
+                };
+                let timeline = timelines.entry(client_id.to_string()).or_default();
+                // Backups arriving while the client is restored show a state in between
+                // actions, `travel_to` adds the one taken once it is done
+                if !timeline.traveling {
+                    timeline.add_checkpoint(state.clone(), options);
+                }
+                false
+            }
+            _ => false,
+        }
+    }
+}
+
+// Keep track of the actions and backups of a client. Called for every
+// command, before answers to requests are handed out.
+pub fn observe(app_handle: &AppHandle, cmd: &Command) {
+    let Some(client_id) = &cmd.client_id else {
+        return;
+    };
+    let backup_due = get_time_travel().lock().unwrap().record(client_id, cmd);
+    if backup_due {
+        spawn_request_backup(app_handle, client_id);
+    }
+}
+
+// Take a backup of clients that dispatched actions since their last one,
+// once `snapshot_interval_ms` went by
+pub fn start_snapshotter(app_handle: AppHandle, options: TimeTravelOptions) -> async_runtime::JoinHandle<()> {
+    let snapshot_interval = Duration::from_millis(options.snapshot_interval_ms.max(1000));
+    get_time_travel().lock().unwrap().options = options;
+
+    async_runtime::spawn(async move {
+        let mut interval = interval(SNAPSHOTTER_TICK);
+
+        loop {
+            interval.tick().await;
+            let mut time_travel = get_time_travel().lock().unwrap();
+            if !time_travel.options.enabled {
+                continue;
+            }
+            for (client_id, timeline) in time_travel.timelines.iter_mut() {
+                let due = timeline
+                    .last_checkpoint_at
+                    .is_none_or(|at| at.elapsed() >= snapshot_interval);
+                if due && !timeline.backup_pending && !timeline.traveling && timeline.actions_since_checkpoint() > 0 {
+                    spawn_backup(&app_handle, client_id, timeline);
+                }
+            }
+        }
+    })
+}
+
+pub fn remove_client(client_id: &str) {
+    get_time_travel().lock().unwrap().timelines.remove(client_id);
+}
+
+pub fn clear() {
+    get_time_travel().lock().unwrap().timelines.clear();
+}
+
+// Timelines keep actions and checkpoints as sent, to restore clients exactly.
+// What the UI gets goes through the redaction rules.
+fn action_info(action: &RecordedAction) -> RecordedAction {
+    let payload = serde_json::json!({ "name": action.name, "action": action.action });
+    RecordedAction {
+        action: redaction::redact_copy("state.action.complete", &payload)["action"].take(),
+        ..action.clone()
+    }
+}
+
+fn checkpoint_info(checkpoint: &Checkpoint) -> CheckpointInfo {
+    CheckpointInfo {
+        position: checkpoint.position,
+        date: checkpoint.date.clone(),
+        size: checkpoint.size,
+    }
+}
+
+// Bring a client back to the state it had right after action `seq`: restore
+// the closest checkpoint before it, then dispatch the actions in between.
+// Whatever was recorded after `seq` is dropped.
+pub async fn travel_to(
+    app_handle: AppHandle,
+    client_id: &str,
+    seq: u64,
+    completion_timeout_ms: Option<u64>,
+) -> Result<TravelResult, String> {
+    if !reactauri_core_server::is_client_connected(client_id).await {
+        return Err(format!("Client {} is not connected", client_id));
+    }
+    let (checkpoint, actions) = {
+        let mut time_travel = get_time_travel().lock().unwrap();
+        let timeline = time_travel
+            .timelines
+            .get_mut(client_id)
+            .ok_or_else(|| format!("No timeline recorded for client {}", client_id))?;
+        if timeline.traveling {
+            return Err(format!("Client {} is already being restored", client_id));
+        }
+        let (checkpoint, actions) = timeline
+            .plan(seq)
+            .map_err(|e| format!("Can't restore client {}: {}", client_id, e))?;
+        timeline.truncate_after(seq);
+        timeline.traveling = true;
+        (checkpoint, actions)
+    };
+    info!(
+        "Restoring client {} to action {}: checkpoint at {} and {} actions",
+        client_id,
+        seq,
+        checkpoint.position,
+        actions.len()
+    );
+
+    let command = CommandWithClientId {
+        r#type: "state.restore.request".to_string(),
+        payload: serde_json::json!({ "state": checkpoint.state }),
+        client_id: client_id.to_string(),
+        important: false,
+        date: Some(chrono::Utc::now().to_rfc3339()),
+        delta_time: Some(0),
+    };
+    reactauri_core_server::send_command(app_handle.clone(), command).await;
+
+    let script = ActionScript {
+        id: format!("time-travel-{}", seq),
+        name: format!("Time travel to action {}", seq),
+        client_id: Some(client_id.to_string()),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        actions: actions
+            .into_iter()
+            .map(|a| ScriptAction {
+                name: a.name,
+                action: a.action,
+                delay_ms: 0,
+            })
+            .collect(),
+    };
+    let options = ReplayOptions {
+        delay_ms: Some(0),
+        speed: 1.0,
+        wait_for_completion: true,
+        completion_timeout_ms,
+        stop_on_error: true,
+    };
+    let replay = action_scripts::replay(app_handle.clone(), &script, client_id, &options).await;
+
+    // Later actions start from the restored state, a new checkpoint marks that
+    let state = if replay.errors.is_empty() {
+        request_backup(app_handle.clone(), client_id.to_string()).await
+    } else {
+        warn!("Restoring client {} to action {} stopped early", client_id, seq);
+        None
+    };
+    {
+        let mut guard = get_time_travel().lock().unwrap();
+        let TimeTravel { options, timelines } = &mut *guard;
+        if let Some(timeline) = timelines.get_mut(client_id) {
+            timeline.traveling = false;
+            match state {
+                Some(state) => timeline.add_checkpoint(state, options),
+                // The client stopped somewhere before `seq`, start over from where it is
+                None if !replay.errors.is_empty() => {
+                    timeline.actions.clear();
+                    timeline.checkpoints.clear();
+                    spawn_backup(&app_handle, client_id, timeline);
+                }
+                None => {}
+            }
+        }
+    }
+
+    Ok(TravelResult {
+        client_id: client_id.to_string(),
+        seq,
+        checkpoint: checkpoint_info(&checkpoint),
+        replay,
+    })
+}
+
+#[tauri::command]
+pub fn get_time_travel_timeline(client_id: String) -> Option<TimelineInfo> {
+    let time_travel = get_time_travel().lock().unwrap();
+    time_travel.timelines.get(&client_id).map(|timeline| TimelineInfo {
+        client_id: client_id.clone(),
+        actions: timeline.actions.iter().map(action_info).collect(),
+        checkpoints: timeline.checkpoints.iter().map(checkpoint_info).collect(),
+    })
+}
+
+#[tauri::command]
+pub async fn time_travel_to(
+    app: AppHandle,
+    client_id: String,
+    seq: u64,
+    timeout_ms: Option<u64>,
+) -> Result<TravelResult, String> {
+    travel_to(app, &client_id, seq, timeout_ms).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn options(max_actions: usize, max_checkpoints: usize) -> TimeTravelOptions {
+        TimeTravelOptions {
+            enabled: true,
+            max_actions,
+            max_checkpoints,
+            ..TimeTravelOptions::default()
+        }
+    }
+
+    // Actions set `count` to their seq, so the state after action n is n
+    fn record(timeline: &mut Timeline, count: u64, options: &TimeTravelOptions) {
+        for _ in 0..count {
+            let seq = timeline.next_seq;
+            timeline.add_action("SET", json!({ "type": "SET", "count": seq }), options);
+        }
+    }
+
+    fn positions(timeline: &Timeline) -> Vec<u64> {
+        timeline.checkpoints.iter().map(|c| c.position).collect()
+    }
+
+    #[test]
+    fn plans_from_the_closest_checkpoint() {
+        let options = options(100, 10);
+        let mut timeline = Timeline::default();
+        timeline.add_checkpoint(json!({ "count": null }), &options);
+        record(&mut timeline, 10, &options);
+        timeline.add_checkpoint(json!({ "count": 9 }), &options);
+        record(&mut timeline, 5, &options);
+
+        let (checkpoint, actions) = timeline.plan(12).unwrap();
+        assert_eq!(checkpoint.position, 10);
+        assert_eq!(actions.iter().map(|a| a.seq).collect::<Vec<_>>(), vec![10, 11, 12]);
+
+        // The checkpoint at 10 is the state right after action 9
+        let (checkpoint, actions) = timeline.plan(9).unwrap();
+        assert_eq!(checkpoint.position, 10);
+        assert!(actions.is_empty());
+
+        let (checkpoint, actions) = timeline.plan(3).unwrap();
+        assert_eq!(checkpoint.position, 0);
+        assert_eq!(actions.len(), 4);
+
+        assert!(timeline.plan(15).is_err());
+    }
+
+    #[test]
+    fn traveling_starts_a_new_branch() {
+        let options = options(100, 10);
+        let mut timeline = Timeline::default();
+        timeline.add_checkpoint(json!({ "count": null }), &options);
+        record(&mut timeline, 10, &options);
+        timeline.add_checkpoint(json!({ "count": 9 }), &options);
+        record(&mut timeline, 5, &options);
+
+        // What `travel_to` does for action 3, the backup taken after the replay
+        // being the state after action 3
+        timeline.plan(3).unwrap();
+        timeline.truncate_after(3);
+        timeline.add_checkpoint(json!({ "count": 3 }), &options);
+        assert_eq!(timeline.next_seq, 4);
+        assert_eq!(positions(&timeline), vec![0, 4]);
+        assert_eq!(timeline.actions.back().map(|a| a.seq), Some(3));
+
+        // Actions recorded after the travel replay from the post-travel checkpoint
+        record(&mut timeline, 3, &options);
+        let (checkpoint, actions) = timeline.plan(5).unwrap();
+        assert_eq!(checkpoint.position, 4);
+        assert_eq!(checkpoint.state, json!({ "count": 3 }));
+        assert_eq!(actions.iter().map(|a| a.seq).collect::<Vec<_>>(), vec![4, 5]);
+        assert_eq!(actions[1].action["count"], 5);
+
+        // Earlier actions still replay from the first checkpoint
+        let (checkpoint, actions) = timeline.plan(2).unwrap();
+        assert_eq!(checkpoint.position, 0);
+        assert_eq!(actions.len(), 3);
+    }
+
+    #[test]
+    fn trimming_actions_drops_checkpoints_they_were_needed_for() {
+        let options = options(5, 10);
+        let mut timeline = Timeline::default();
+        timeline.add_checkpoint(json!({}), &options);
+        record(&mut timeline, 4, &options);
+        timeline.add_checkpoint(json!({ "count": 3 }), &options);
+        record(&mut timeline, 4, &options);
+
+        // Dropping action 0 made the checkpoint at 0 useless, and with it actions 1 to 3
+        assert_eq!(timeline.actions.front().map(|a| a.seq), Some(4));
+        assert_eq!(timeline.actions.len(), 4);
+        assert_eq!(positions(&timeline), vec![4]);
+
+        assert!(timeline.plan(2).is_err());
+        let (checkpoint, actions) = timeline.plan(6).unwrap();
+        assert_eq!(checkpoint.position, 4);
+        assert_eq!(actions.len(), 3);
+
+        // Past the checkpoint, nothing can be replayed anymore
+        record(&mut timeline, 5, &options);
+        assert!(timeline.checkpoints.is_empty());
+        assert!(timeline.plan(11).is_err());
+    }
+
+    #[test]
+    fn refuses_to_replay_over_missing_actions() {
+        let options = options(100, 10);
+        let mut timeline = Timeline::default();
+        timeline.add_checkpoint(json!({}), &options);
+        record(&mut timeline, 6, &options);
+        timeline.actions.retain(|a| a.seq != 2);
+
+        assert!(timeline.plan(1).is_ok());
+        assert!(timeline.plan(4).is_err());
+    }
+
+    #[test]
+    fn keeps_the_newest_checkpoints() {
+        let options = options(100, 2);
+        let mut timeline = Timeline::default();
+        for _ in 0..4 {
+            timeline.add_checkpoint(json!({}), &options);
+            record(&mut timeline, 2, &options);
+        }
+
+        assert_eq!(positions(&timeline), vec![4, 6]);
+        assert_eq!(timeline.actions.front().map(|a| a.seq), Some(4));
+        assert_eq!(timeline.actions_since_checkpoint(), 2);
+    }
+
+    fn command(command_type: &str, payload: Value) -> Command {
+        serde_json::from_value(json!({ "type": command_type, "payload": payload, "clientId": "app" })).unwrap()
+    }
+
+    #[test]
+    fn records_actions_and_backups_as_sent() {
+        redaction::use_test_rules();
+        let mut time_travel = TimeTravel {
+            options: options(100, 10),
+            timelines: HashMap::new(),
+        };
+
+        assert!(time_travel.record("app", &command("client.intro", json!({}))));
+        assert!(!time_travel.record("app", &command("state.backup.response", json!({ "state": { "token": "secret-1" } }))));
+        time_travel.timelines.get_mut("app").unwrap().backup_pending = false;
+        let action = json!({ "type": "LOGIN", "token": "secret-2" });
+        time_travel.record("app", &command("state.action.complete", json!({ "name": "LOGIN", "action": action })));
+
+        let (checkpoint, actions) = time_travel.timelines["app"].plan(0).unwrap();
+        assert_eq!(checkpoint.state, json!({ "token": "secret-1" }));
+        assert_eq!(actions[0].action, action);
+        assert_eq!(action_info(&actions[0]).action["token"], "[REDACTED]");
+    }
+}
@@ -0,0 +1,100 @@
+use crate::parse_errors::ParseError;
+use crate::reactauri_core_server::Command;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// Encoding a client uses for its commands. JSON travels in text frames, the
+// binary encodings in binary frames. A client picks one with the `encoding`
+// field of its `client.intro` payload and gets replies in the same encoding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WireEncoding {
+    #[default]
+    Json,
+    #[serde(rename = "msgpack")]
+    MessagePack,
+    Cbor,
+}
+
+impl WireEncoding {
+    // Encoding requested in a `client.intro` payload, if any
+    pub fn from_intro(payload: &Value) -> Option<Self> {
+        payload
+            .get("encoding")
+            .and_then(|encoding| Self::deserialize(encoding).ok())
+    }
+
+    // Guess the encoding of a binary frame from its first byte. A command is a
+    // map, and MessagePack (0x80-0x8f, 0xde, 0xdf) and CBOR (0xa0-0xbf) maps
+    // start with different bytes.
+    pub fn sniff(bytes: &[u8]) -> Self {
+        match bytes.first() {
+            Some(0xa0..=0xbf) => Self::Cbor,
+            _ => Self::MessagePack,
+        }
+    }
+
+    pub fn decode(self, bytes: &[u8]) -> Result<Command, ParseError> {
+        match self {
+            Self::Json => serde_json::from_slice(bytes).map_err(ParseError::from),
+            Self::MessagePack => rmp_serde::from_slice(bytes).map_err(ParseError::from),
+            Self::Cbor => ciborium::from_reader(bytes).map_err(ParseError::from),
+        }
+    }
+
+    pub fn encode(self, value: &Value) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Self::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            Self::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes).map_err(|e| e.to_string())?;
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn command() -> Value {
+        json!({ "type": "log", "payload": { "level": "debug", "message": "hi" }, "important": false })
+    }
+
+    // A map with more than 23 entries, past the short forms of both encodings
+    fn big_map() -> Value {
+        Value::Object((0..30).map(|i| (format!("k{}", i), Value::from(i))).collect())
+    }
+
+    #[test]
+    fn sniff_tells_message_pack_and_cbor_maps_apart() {
+        for encoding in [WireEncoding::MessagePack, WireEncoding::Cbor] {
+            assert_eq!(WireEncoding::sniff(&encoding.encode(&command()).unwrap()), encoding);
+            assert_eq!(WireEncoding::sniff(&encoding.encode(&big_map()).unwrap()), encoding);
+        }
+        assert_eq!(WireEncoding::sniff(&[0xbf]), WireEncoding::Cbor);
+        assert_eq!(WireEncoding::sniff(&[0xde, 0x00, 0x10]), WireEncoding::MessagePack);
+        assert_eq!(WireEncoding::sniff(&[]), WireEncoding::MessagePack);
+    }
+
+    #[test]
+    fn commands_round_trip_in_every_encoding() {
+        for encoding in [WireEncoding::Json, WireEncoding::MessagePack, WireEncoding::Cbor] {
+            let cmd = encoding.decode(&encoding.encode(&command()).unwrap()).unwrap();
+            assert_eq!(cmd.r#type, "log");
+            assert_eq!(cmd.payload, json!({ "level": "debug", "message": "hi" }));
+        }
+        assert!(WireEncoding::Cbor.decode(&[0xa1]).is_err());
+    }
+
+    #[test]
+    fn from_intro_reads_the_requested_encoding() {
+        assert_eq!(WireEncoding::from_intro(&json!({ "encoding": "msgpack" })), Some(WireEncoding::MessagePack));
+        assert_eq!(WireEncoding::from_intro(&json!({ "encoding": "cbor" })), Some(WireEncoding::Cbor));
+        assert_eq!(WireEncoding::from_intro(&json!({ "encoding": "xml" })), None);
+        assert_eq!(WireEncoding::from_intro(&json!({ "name": "app" })), None);
+    }
+}
@@ -36,6 +36,7 @@ const Provider: React.FC<{ children: React.ReactNode }> = ({ children }) => {
     serverStopped,
     connectionEstablished,
     commandReceived,
+    commandsReceived,
     connectionDisconnected,
     addCommandListener,
     portUnavailable,
@@ -74,6 +75,11 @@ const Provider: React.FC<{ children: React.ReactNode }> = ({ children }) => {
 
     })
 
+    const unlistenCommands = listen<any[]>('commands', (event) => {
+      // Batched commands, emitted by the core server when traffic is high
+      commandsReceived(event.payload.map((command) => repairSerialization(command)))
+    })
+
     invoke('start_core_server')
     
     return () => {
@@ -81,6 +87,7 @@ const Provider: React.FC<{ children: React.ReactNode }> = ({ children }) => {
       unlistenStop?.then((unlisten) => unlisten())
       unlistenConnectionEstablished?.then((unlisten) => unlisten())
       unlistenCommnad?.then((unlisten) => unlisten())
+      unlistenCommands?.then((unlisten) => unlisten())
       unlistenDisconnect?.then((unlisten) => unlisten())
       unlistenPortUnavailable?.then((unlisten) => unlisten())
     }
@@ -89,6 +96,7 @@ const Provider: React.FC<{ children: React.ReactNode }> = ({ children }) => {
     serverStopped,
     connectionEstablished,
     commandReceived,
+    commandsReceived,
     connectionDisconnected,
     portUnavailable,
   ])
@@ -311,6 +311,33 @@ describe("contexts/Standalone/useStandalone", () => {
       expect(result.current.connections[0].commands.length).toEqual(0)
     })
 
+    it("should add a batch of commands in one update, newest first", () => {
+      const { result } = renderHook(() => useStandalone())
+
+      act(() => {
+        result.current.connectionEstablished({
+          clientId: "1234",
+          id: 0,
+          platform: "ios",
+        })
+      })
+
+      act(() => {
+        result.current.commandReceived({ clientId: "1234", payload: 1 })
+      })
+
+      act(() => {
+        result.current.commandsReceived([
+          { clientId: "1234", payload: 2 },
+          { connectionId: 0, payload: 3 },
+          { clientId: "1234", payload: 4 },
+        ])
+      })
+
+      expect(result.current.connections[0].commands.map((c) => c.payload)).toEqual([4, 2, 1])
+      expect(result.current.orphanedCommands).toEqual([{ connectionId: 0, payload: 3 }])
+    })
+
     it("should add a command received listener and it should be called when a command is received", () => {
       const { result } = renderHook(() => useStandalone())
       const mockListener = jest.fn()
@@ -8,6 +8,7 @@ export enum ActionTypes {
   RemoveConnection = "REMOVE_CONNECTION",
   ClearConnectionCommands = "CLEAR_CONNECTION_COMMANDS",
   CommandReceived = "COMMAND_RECEIVED",
+  CommandsReceived = "COMMANDS_RECEIVED",
   ChangeSelectedClientId = "CHANGE_SELECTED_CLIENT_ID",
   AddCommandHandler = "ADD_COMMAND_HANDLER",
   PortUnavailable = "PORT_UNAVAILABLE",
@@ -51,6 +52,7 @@ type Action =
     }
   | { type: ActionTypes.ChangeSelectedClientId; payload: string }
   | { type: ActionTypes.CommandReceived; payload: any } // TODO: Type this better!
+  | { type: ActionTypes.CommandsReceived; payload: any[] }
   | { type: ActionTypes.ClearConnectionCommands }
   | { type: ActionTypes.AddCommandHandler; payload: (command: any) => void }
   | { type: ActionTypes.PortUnavailable; payload: undefined }
@@ -120,6 +122,32 @@ const sessionStorage = {
   }
 }
 
+// Add commands, oldest first, to their connections, newest ending up at the top
+function addCommands(draftState: State, commands: any[]) {
+  const received = new Map<Connection, any[]>()
+
+  commands.forEach((command) => {
+    if (!command.clientId) {
+      draftState.orphanedCommands.push(command)
+      return
+    }
+
+    const connection = draftState.connections.find((c) => c.clientId === command.clientId)
+
+    if (!connection) {
+      console.error("Command received for unknown connection:", command)
+      return
+    }
+
+    if (!received.has(connection)) received.set(connection, [])
+    received.get(connection).unshift(command)
+  })
+
+  received.forEach((newCommands, connection) => {
+    connection.commands = [...newCommands, ...connection.commands]
+  })
+}
+
 export function reducer(state: State, action: Action) {
   switch (action.type) {
     case ActionTypes.ServerStarted:
@@ -213,21 +241,11 @@ export function reducer(state: State, action: Action) {
 
     case ActionTypes.CommandReceived:
       return produce(state, (draftState) => {
-        if (!action.payload.clientId) {
-          draftState.orphanedCommands.push(action.payload)
-          return
-        }
-
-        const connection = draftState.connections.find(
-          (c) => c.clientId === action.payload.clientId
-        )
-
-        if (!connection) {
-          console.error("Command received for unknown connection:", action.payload)
-          return
-        }
-
-        connection.commands = [action.payload, ...connection.commands]
+        addCommands(draftState, [action.payload])
+      })
+    case ActionTypes.CommandsReceived:
+      return produce(state, (draftState) => {
+        addCommands(draftState, action.payload)
       })
     case ActionTypes.ClearConnectionCommands:
       return produce(state, (draftState) => {
@@ -330,6 +348,16 @@ function useStandalone() {
     [state.commandListeners]
   )
 
+  // Called with a batch of commands, applied in a single state update
+  const commandsReceived = useCallback(
+    (commands: any[]) => {
+      dispatch({ type: ActionTypes.CommandsReceived, payload: commands })
+
+      commands.forEach((command) => state.commandListeners.forEach((cl) => cl(command)))
+    },
+    [state.commandListeners]
+  )
+
   // Called when a client disconnects. NOTE: They could be coming back. This could happen with a reload of the simulator!
   const connectionDisconnected = useCallback((connection: ReactotronConnection) => {
     dispatch({ type: ActionTypes.RemoveConnection, payload: connection })
@@ -360,6 +388,7 @@ function useStandalone() {
     connectionEstablished,
     connectionDisconnected,
     commandReceived,
+    commandsReceived,
     clearSelectedConnectionCommands,
     addCommandListener,
     portUnavailable,
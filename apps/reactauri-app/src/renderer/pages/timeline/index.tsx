@@ -1,5 +1,6 @@
-import React, { useCallback, useContext, useMemo } from "react"
+import React, { useCallback, useContext, useMemo, useState } from "react"
 import * as path from '@tauri-apps/api/path';
+import { invoke } from "@tauri-apps/api/core"
 import { writeText } from '@tauri-apps/plugin-clipboard-manager';
 import debounce from "lodash.debounce"
 import {
@@ -25,6 +26,7 @@ import { FaTimes } from "react-icons/fa"
 import styled from "styled-components"
 import { openUrl } from '@tauri-apps/plugin-opener';
 import { readTextFile, writeTextFile } from "@tauri-apps/plugin-fs";
+import repairSerialization from "../../util/repair-serialization"
 
 const Container = styled.div`
   display: flex;
@@ -102,13 +104,30 @@ function Timeline() {
     setHiddenCommands,
   } = useContext(TimelineContext)
 
+  // Large payloads arrive as a preview with a handle, the full payload is
+  // fetched when the item is opened
+  const [fullPayloads, setFullPayloads] = useState<Record<number, unknown>>({})
+  const fetchFullPayload = useCallback((command: any) => {
+    invoke("get_payload", { handle: command.payloadHandle })
+      .then((payload) => {
+        setFullPayloads((loaded) => ({ ...loaded, [command.messageId]: repairSerialization(payload) }))
+      })
+      .catch((error) => console.error(error))
+  }, [])
+
   const renderCommandItem = useCallback((command: any) => {
     const CommandComponent = timelineCommandResolver(command.type)
     if (CommandComponent) {
+      const fullPayload = fullPayloads[command.messageId]
       return (
         <CommandComponent
           key={command.messageId}
-          command={command}
+          command={fullPayload === undefined ? command : { ...command, payload: fullPayload }}
+          onExpandChange={(_messageId, isOpen) => {
+            if (isOpen && command.payloadHandle && fullPayload === undefined) {
+              fetchFullPayload(command)
+            }
+          }}
           copyToClipboard={writeText}
           readFile={async (filePath) => {
             try {
@@ -127,7 +146,7 @@ function Timeline() {
       )
     }
     return null
-  }, [writeText, readTextFile, sendCommand, openDispatchModal])
+  }, [writeText, readTextFile, sendCommand, openDispatchModal, fullPayloads, fetchFullPayload])
 
   let filteredCommands: unknown[] = []
   try {
@@ -25,6 +25,10 @@ export enum ArgType {
 export interface CustomCommandArg {
   name: string
   type: ArgType
+  // The app may leave the argument out
+  optional?: boolean
+  // Sent in place of the argument when it is left out
+  default?: string
 }
 
 // #region Plugin Types